@@ -0,0 +1,271 @@
+//! Burst accounts: ids derived from secret phrases and their Reed-Solomon addresses.
+
+use crate::config::Cfg;
+use ring::digest::{digest, SHA256};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::RwLock;
+use x25519_dalek::{x25519, X25519_BASEPOINT_BYTES};
+
+const RS_PREFIX: &str = "BURST-";
+const RS_ALPHABET: &[u8; 32] = b"23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+// 13 base 32 digits of the id followed by 4 parity symbols, in the order they are written
+const RS_CODEWORD_MAP: [usize; 17] = [3, 2, 1, 0, 7, 6, 5, 4, 13, 14, 15, 16, 12, 8, 9, 10, 11];
+const RS_DATA_LEN: usize = 13;
+const GEXP: [u8; 32] = [
+    1, 2, 4, 8, 16, 5, 10, 20, 13, 26, 17, 7, 14, 28, 29, 31, 27, 19, 3, 6, 12, 24, 21, 15, 30, 25,
+    23, 11, 22, 9, 18, 1,
+];
+const GLOG: [u8; 32] = [
+    0, 0, 1, 18, 2, 5, 19, 11, 3, 29, 6, 27, 20, 8, 12, 23, 4, 10, 30, 17, 7, 22, 28, 26, 21, 25,
+    9, 16, 13, 14, 24, 15,
+];
+
+// how accounts are shown in logs, configured once at startup
+static DISPLAY: RwLock<Option<AccountDisplay>> = RwLock::new(None);
+
+struct AccountDisplay {
+    show_rs_addresses: bool,
+    aliases: HashMap<u64, String>,
+}
+
+impl AccountDisplay {
+    fn show(&self, account_id: u64) -> String {
+        let id = if self.show_rs_addresses {
+            to_rs_address(account_id)
+        } else {
+            account_id.to_string()
+        };
+        match self.aliases.get(&account_id) {
+            Some(alias) => format!("{} ({})", alias, id),
+            None => id,
+        }
+    }
+}
+
+/// An account id as shown in logs and status output, depending on `show_rs_addresses` and
+/// `account_aliases`.
+pub struct Account(pub u64);
+
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &*DISPLAY.read().unwrap() {
+            Some(display) => f.write_str(&display.show(self.0)),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// Sets how `Account`s are shown from now on.
+pub fn init_display(cfg: &Cfg) {
+    *DISPLAY.write().unwrap() = Some(AccountDisplay {
+        show_rs_addresses: cfg.show_rs_addresses,
+        aliases: cfg.account_aliases.clone(),
+    });
+}
+
+fn gmult(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    GEXP[(GLOG[a as usize] as usize + GLOG[b as usize] as usize) % 31]
+}
+
+fn is_codeword_valid(codeword: &[u8; 17]) -> bool {
+    (1..5).all(|i| {
+        let mut t = 0;
+        for j in (0..13).chain(27..31) {
+            let pos = if j > 26 { j - 14 } else { j };
+            t ^= gmult(codeword[pos], GEXP[(i * j) % 31]);
+        }
+        t == 0
+    })
+}
+
+/// The Reed-Solomon address of an account, e.g. `BURST-XXXX-XXXX-XXXX-XXXXX`.
+pub fn to_rs_address(account_id: u64) -> String {
+    let mut codeword = [0u8; 17];
+    for (i, digit) in codeword.iter_mut().take(RS_DATA_LEN).enumerate() {
+        *digit = ((account_id >> (5 * i)) & 31) as u8;
+    }
+
+    let mut p = [0u8; 4];
+    for i in (0..RS_DATA_LEN).rev() {
+        let fb = codeword[i] ^ p[3];
+        p[3] = p[2] ^ gmult(30, fb);
+        p[2] = p[1] ^ gmult(6, fb);
+        p[1] = p[0] ^ gmult(9, fb);
+        p[0] = gmult(17, fb);
+    }
+    codeword[RS_DATA_LEN..].copy_from_slice(&p);
+
+    let mut address = RS_PREFIX.to_owned();
+    for (i, &pos) in RS_CODEWORD_MAP.iter().enumerate() {
+        address.push(RS_ALPHABET[codeword[pos] as usize] as char);
+        if i & 3 == 3 && i < RS_DATA_LEN {
+            address.push('-');
+        }
+    }
+    address
+}
+
+/// Parses numeric account ids as well as Reed-Solomon addresses, the `BURST-` prefix is
+/// optional.
+pub fn parse_account_id(account: &str) -> Result<u64, String> {
+    let account = account.trim();
+    if let Ok(account_id) = account.parse() {
+        return Ok(account_id);
+    }
+
+    let error = || format!("invalid account id or address: {}", account);
+    let upper = account.to_uppercase();
+    let address = upper.trim_start_matches(RS_PREFIX);
+    let mut codeword = [0u8; 17];
+    let mut len = 0;
+    for c in address.bytes().filter(|&c| c != b'-') {
+        let digit = RS_ALPHABET.iter().position(|&a| a == c).ok_or_else(error)?;
+        if len == codeword.len() {
+            return Err(error());
+        }
+        codeword[RS_CODEWORD_MAP[len]] = digit as u8;
+        len += 1;
+    }
+    // 13 digits carry 65 bits
+    if len != codeword.len() || !is_codeword_valid(&codeword) || codeword[RS_DATA_LEN - 1] > 15 {
+        return Err(error());
+    }
+    Ok(codeword[..RS_DATA_LEN]
+        .iter()
+        .enumerate()
+        .fold(0, |id, (i, &digit)| id | (u64::from(digit) << (5 * i))))
+}
+
+/// The Curve25519 public key of a secret phrase, its private key is the phrase's SHA-256.
+pub fn public_key(secret_phrase: &str) -> [u8; 32] {
+    let mut private_key = [0u8; 32];
+    private_key.copy_from_slice(digest(&SHA256, secret_phrase.as_bytes()).as_ref());
+    x25519(private_key, X25519_BASEPOINT_BYTES)
+}
+
+/// The account id of a secret phrase: the first 8 bytes of its public key's SHA-256.
+pub fn account_id(secret_phrase: &str) -> u64 {
+    let hash = digest(&SHA256, &public_key(secret_phrase));
+    let mut id = [0u8; 8];
+    id.copy_from_slice(&hash.as_ref()[..8]);
+    u64::from_le_bytes(id)
+}
+
+/// Makes sure every secret phrase belongs to the account it is configured for, a wrong one
+/// would only show when a block is rejected.
+pub fn check_secret_phrases(cfg: &Cfg) -> Result<(), Box<dyn Error>> {
+    let secret_phrases = cfg.account_id_to_secret_phrase.iter().chain(
+        cfg.account_id_to_upstream
+            .iter()
+            .filter_map(|(id, upstream)| upstream.secret_phrase.as_ref().map(|s| (id, s))),
+    );
+    let mismatches: Vec<String> = secret_phrases
+        .filter_map(|(&id, secret_phrase)| {
+            let derived_id = account_id(secret_phrase.expose());
+            if derived_id == id {
+                None
+            } else {
+                Some(format!(
+                    "the secret phrase of account {} belongs to account {}",
+                    id, derived_id
+                ))
+            }
+        })
+        .collect();
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(From::from(mismatches.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{load_cfg, SecretPhrase};
+
+    #[test]
+    fn test_account_id() {
+        assert_eq!(
+            account_id("glad suffer red during single glow shut slam hill death lust although"),
+            10282355196851764065
+        );
+        assert_eq!(
+            account_id(
+                "stand rude those door invite reflection anywhere lace safe hidden fur horrible"
+            ),
+            1796535821016683299
+        );
+    }
+
+    #[test]
+    fn test_rs_address() {
+        for &account_id in &[0, 1, 10282355196851764065, 1796535821016683299, u64::MAX] {
+            let address = to_rs_address(account_id);
+            assert_eq!(address.len(), 26);
+            assert_eq!(parse_account_id(&address), Ok(account_id));
+            assert_eq!(
+                parse_account_id(&address[6..].to_lowercase()),
+                Ok(account_id)
+            );
+        }
+        assert_eq!(
+            parse_account_id("1796535821016683299"),
+            Ok(1796535821016683299)
+        );
+
+        // the donation addresses of the README
+        for address in &["BURST-8V9Y-58B4-RVWP-8HQAV", "BURST-S338-R6VC-LTFA-2GC6G"] {
+            assert_eq!(to_rs_address(parse_account_id(address).unwrap()), *address);
+        }
+
+        // a single typo is caught by the parity symbols
+        let mut address = to_rs_address(10282355196851764065).into_bytes();
+        address[7] = if address[7] == b'2' { b'3' } else { b'2' };
+        assert!(parse_account_id(&String::from_utf8(address).unwrap()).is_err());
+        assert!(parse_account_id("BURST-2222").is_err());
+        assert!(parse_account_id("BURST-O222-2222-2222-22222").is_err());
+    }
+
+    #[test]
+    fn test_account_display() {
+        let mut aliases = HashMap::new();
+        aliases.insert(1796535821016683299, "johnny".to_owned());
+        let display = AccountDisplay {
+            show_rs_addresses: false,
+            aliases,
+        };
+        assert_eq!(
+            display.show(1796535821016683299),
+            "johnny (1796535821016683299)"
+        );
+        assert_eq!(display.show(1), "1");
+
+        let display = AccountDisplay {
+            show_rs_addresses: true,
+            ..display
+        };
+        assert_eq!(
+            display.show(1796535821016683299),
+            "johnny (BURST-FNT5-TY8Z-78TF-3MNVK)"
+        );
+    }
+
+    #[test]
+    fn test_check_secret_phrases() {
+        let mut cfg = load_cfg("config.yaml");
+        assert!(check_secret_phrases(&cfg).is_ok());
+
+        cfg.account_id_to_secret_phrase
+            .insert(1, SecretPhrase::new("glad suffer".to_owned()));
+        let e = check_secret_phrases(&cfg).unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with("the secret phrase of account 1 belongs to"));
+    }
+}
@@ -13,3 +13,24 @@ void write_term(char term[32]) {
     term[0] = -128;  // shabal message termination bit
     memset(&term[1], 0, 31);
 }
+
+// gendata holds NONCE_SIZE bytes of hashes followed by the 16 byte seed
+void write_nonce_seed(char *gendata, uint64_t numeric_id, uint64_t nonce) {
+    numeric_id = bswap_64(numeric_id);
+    nonce = bswap_64(nonce);
+    memmove(&gendata[NONCE_SIZE], &numeric_id, 8);
+    memmove(&gendata[NONCE_SIZE + 8], &nonce, 8);
+}
+
+// xor the nonce with its final hash and scatter it into a scoop-major PoC2 cache
+void write_nonce_poc2(char *cache, size_t cache_size, size_t nonce_offset, char *gendata,
+                      const char final[32]) {
+    for (size_t i = 0; i < NONCE_SIZE; i++) gendata[i] ^= final[i % HASH_SIZE];
+    for (size_t i = 0; i < NUM_SCOOPS; i++) {
+        char *scoop = &cache[(i * cache_size + nonce_offset) * SCOOP_SIZE];
+        memcpy(scoop, &gendata[i * SCOOP_SIZE], HASH_SIZE);
+        // PoC2: the second hash of scoop i is taken from scoop 4095 - i
+        memcpy(&scoop[HASH_SIZE], &gendata[(NUM_SCOOPS - i - 1) * SCOOP_SIZE + HASH_SIZE],
+               HASH_SIZE);
+    }
+}
@@ -1,3 +1,4 @@
+#include <stddef.h>
 #include <stdint.h>
 
 #pragma once
@@ -58,6 +59,11 @@ void write_seed(char seed[32], uint64_t numeric_id);
 
 void write_term(char term[32]);
 
+void write_nonce_seed(char *gendata, uint64_t numeric_id, uint64_t nonce);
+
+void write_nonce_poc2(char *cache, size_t cache_size, size_t nonce_offset, char *gendata,
+                      const char final[32]);
+
 #define SET_BEST_DEADLINE(d, o) \
     if ((d) < *best_deadline) { \
         *best_deadline = (d);   \
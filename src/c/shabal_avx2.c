@@ -93,3 +93,40 @@ void find_best_deadline_avx2(char *scoops, uint64_t nonce_count, char *gensig,
         }
     }
 }
+
+// generates PoC2 nonces into a scoop-major cache, local_nonces has to be a multiple of 8
+void noncegen_avx2(char *cache, size_t cache_size, size_t chunk_offset, uint64_t numeric_id,
+                   uint64_t local_startnonce, uint64_t local_nonces) {
+    mshabal256_context x;
+    char final[MSHABAL256_VECTOR_SIZE][HASH_SIZE];
+    char *g[MSHABAL256_VECTOR_SIZE];
+    char *gendata = (char *)malloc(MSHABAL256_VECTOR_SIZE * (NONCE_SIZE + 16));
+    for (int j = 0; j < MSHABAL256_VECTOR_SIZE; j++) g[j] = &gendata[j * (NONCE_SIZE + 16)];
+
+    for (uint64_t n = 0; n + MSHABAL256_VECTOR_SIZE <= local_nonces; n += MSHABAL256_VECTOR_SIZE) {
+        for (int j = 0; j < MSHABAL256_VECTOR_SIZE; j++)
+            write_nonce_seed(g[j], numeric_id, local_startnonce + n + j);
+
+        for (size_t i = NONCE_SIZE; i > 0; i -= HASH_SIZE) {
+            size_t len = NONCE_SIZE + 16 - i;
+            if (len > HASH_CAP) len = HASH_CAP;
+            memcpy(&x, &global_256, sizeof(global_256));
+            mshabal_avx2(&x, &g[0][i], &g[1][i], &g[2][i], &g[3][i], &g[4][i], &g[5][i], &g[6][i],
+                         &g[7][i], len);
+            mshabal_close_avx2(&x, 0, 0, 0, 0, 0, 0, 0, 0, 0, &g[0][i - HASH_SIZE],
+                               &g[1][i - HASH_SIZE], &g[2][i - HASH_SIZE], &g[3][i - HASH_SIZE],
+                               &g[4][i - HASH_SIZE], &g[5][i - HASH_SIZE], &g[6][i - HASH_SIZE],
+                               &g[7][i - HASH_SIZE]);
+        }
+
+        // final hash over the whole nonce including the seed
+        memcpy(&x, &global_256, sizeof(global_256));
+        mshabal_avx2(&x, g[0], g[1], g[2], g[3], g[4], g[5], g[6], g[7], NONCE_SIZE + 16);
+        mshabal_close_avx2(&x, 0, 0, 0, 0, 0, 0, 0, 0, 0, final[0], final[1], final[2], final[3],
+                           final[4], final[5], final[6], final[7]);
+
+        for (int j = 0; j < MSHABAL256_VECTOR_SIZE; j++)
+            write_nonce_poc2(cache, cache_size, chunk_offset + n + j, g[j], final[j]);
+    }
+    free(gendata);
+}
@@ -7,3 +7,6 @@ void init_shabal_neon();
 
 void find_best_deadline_neon(char *scoops, uint64_t nonce_count, char *gensig,
                              uint64_t *best_deadline, uint64_t *best_offset);
+
+void noncegen_neon(char *cache, size_t cache_size, size_t chunk_offset, uint64_t numeric_id,
+                   uint64_t local_startnonce, uint64_t local_nonces);
@@ -73,3 +73,36 @@ void find_best_deadline_sse2(char *scoops, uint64_t nonce_count, char *gensig,
         }
     }
 }
+
+// generates PoC2 nonces into a scoop-major cache, local_nonces has to be a multiple of 4
+void noncegen_sse2(char *cache, size_t cache_size, size_t chunk_offset, uint64_t numeric_id,
+                   uint64_t local_startnonce, uint64_t local_nonces) {
+    mshabal128_context x;
+    char final[MSHABAL128_VECTOR_SIZE][HASH_SIZE];
+    char *g[MSHABAL128_VECTOR_SIZE];
+    char *gendata = (char *)malloc(MSHABAL128_VECTOR_SIZE * (NONCE_SIZE + 16));
+    for (int j = 0; j < MSHABAL128_VECTOR_SIZE; j++) g[j] = &gendata[j * (NONCE_SIZE + 16)];
+
+    for (uint64_t n = 0; n + MSHABAL128_VECTOR_SIZE <= local_nonces; n += MSHABAL128_VECTOR_SIZE) {
+        for (int j = 0; j < MSHABAL128_VECTOR_SIZE; j++)
+            write_nonce_seed(g[j], numeric_id, local_startnonce + n + j);
+
+        for (size_t i = NONCE_SIZE; i > 0; i -= HASH_SIZE) {
+            size_t len = NONCE_SIZE + 16 - i;
+            if (len > HASH_CAP) len = HASH_CAP;
+            memcpy(&x, &global_128, sizeof(global_128));
+            mshabal_sse2(&x, &g[0][i], &g[1][i], &g[2][i], &g[3][i], len);
+            mshabal_close_sse2(&x, 0, 0, 0, 0, 0, &g[0][i - HASH_SIZE], &g[1][i - HASH_SIZE],
+                               &g[2][i - HASH_SIZE], &g[3][i - HASH_SIZE]);
+        }
+
+        // final hash over the whole nonce including the seed
+        memcpy(&x, &global_128, sizeof(global_128));
+        mshabal_sse2(&x, g[0], g[1], g[2], g[3], NONCE_SIZE + 16);
+        mshabal_close_sse2(&x, 0, 0, 0, 0, 0, final[0], final[1], final[2], final[3]);
+
+        for (int j = 0; j < MSHABAL128_VECTOR_SIZE; j++)
+            write_nonce_poc2(cache, cache_size, chunk_offset + n + j, g[j], final[j]);
+    }
+    free(gendata);
+}
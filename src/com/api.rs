@@ -26,7 +26,7 @@ pub struct SubmitNonceResponse {
     pub deadline: u64,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MiningInfoResponse {
     pub generation_signature: String,
@@ -39,7 +39,8 @@ pub struct MiningInfoResponse {
 
     #[serde(
         default = "default_target_deadline",
-        deserialize_with = "from_str_or_int"
+        deserialize_with = "from_str_or_int",
+        skip_serializing_if = "is_default_target_deadline"
     )]
     pub target_deadline: u64,
 }
@@ -48,6 +49,10 @@ fn default_target_deadline() -> u64 {
     std::u64::MAX
 }
 
+fn is_default_target_deadline(target_deadline: &u64) -> bool {
+    *target_deadline == default_target_deadline()
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct PoolErrorWrapper {
@@ -1,4 +1,5 @@
 use crate::com::api::*;
+use crate::config::SecretPhrase;
 use futures::stream::Stream;
 use futures::Future;
 use reqwest::header::{HeaderMap, HeaderName};
@@ -6,6 +7,7 @@ use reqwest::r#async::{Client as InnerClient, ClientBuilder, Decoder};
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 use std::sync::Arc;
 use std::time::Duration;
 use url::form_urlencoded::byte_serialize;
@@ -15,9 +17,11 @@ use url::Url;
 #[derive(Clone, Debug)]
 pub struct Client {
     inner: InnerClient,
-    account_id_to_secret_phrase: Arc<HashMap<u64, String>>,
+    account_id_to_secret_phrase: Arc<HashMap<u64, SecretPhrase>>,
+    secret_phrase_in_query: bool,
     base_uri: Url,
-    total_size_gb: usize,
+    total_size_gb: Arc<AtomicUsize>,
+    report_capacity: bool,
     headers: Arc<HeaderMap>,
 }
 
@@ -116,16 +120,26 @@ impl Client {
     /// Create a new client communicating with Pool/Proxy/Wallet.
     pub fn new(
         base_uri: Url,
-        mut secret_phrases: HashMap<u64, String>,
+        secret_phrases: HashMap<u64, SecretPhrase>,
+        secret_phrase_in_query: bool,
         timeout: u64,
         total_size_gb: usize,
         proxy_details: ProxyDetails,
         additional_headers: HashMap<String, String>,
     ) -> Self {
-        for secret_phrase in secret_phrases.values_mut() {
-            *secret_phrase = byte_serialize(secret_phrase.as_bytes()).collect();
-        }
+        let secret_phrases = secret_phrases
+            .into_iter()
+            .map(|(account_id, secret_phrase)| {
+                let encoded = byte_serialize(secret_phrase.expose().as_bytes()).collect();
+                (account_id, SecretPhrase::new(encoded))
+            })
+            .collect();
 
+        // capacity changes when plots are added or removed, unless it is overwritten
+        let report_capacity = proxy_details == ProxyDetails::Enabled
+            && !additional_headers
+                .keys()
+                .any(|key| key.eq_ignore_ascii_case("X-Capacity"));
         let headers =
             Client::submit_nonce_headers(proxy_details, total_size_gb, additional_headers);
 
@@ -137,17 +151,34 @@ impl Client {
         Self {
             inner: client,
             account_id_to_secret_phrase: Arc::new(secret_phrases),
+            secret_phrase_in_query,
             base_uri,
-            total_size_gb,
+            total_size_gb: Arc::new(AtomicUsize::new(total_size_gb)),
+            report_capacity,
             headers: Arc::new(headers),
         }
     }
 
+    /// Updates the capacity sent along with requests.
+    pub fn set_total_size_gb(&self, total_size_gb: usize) {
+        self.total_size_gb
+            .store(total_size_gb, AtomicOrdering::Relaxed);
+    }
+
+    fn headers(&self) -> HeaderMap {
+        let mut headers = (*self.headers).clone();
+        if self.report_capacity {
+            let total_size_gb = self.total_size_gb.load(AtomicOrdering::Relaxed);
+            headers.insert("X-Capacity", total_size_gb.to_string().parse().unwrap());
+        }
+        headers
+    }
+
     /// Get current mining info.
     pub fn get_mining_info(&self) -> impl Future<Item = MiningInfoResponse, Error = FetchError> {
         self.inner
             .get(self.uri_for("burst"))
-            .headers((*self.headers).clone())
+            .headers(self.headers())
             .query(&GetMiningInfoRequest {
                 request_type: &"getMiningInfo",
             })
@@ -163,6 +194,10 @@ impl Client {
             })
     }
 
+    pub fn base_uri(&self) -> &Url {
+        &self.base_uri
+    }
+
     pub fn uri_for(&self, path: &str) -> Url {
         let mut url = self.base_uri.clone();
         url.path_segments_mut()
@@ -178,28 +213,34 @@ impl Client {
         &self,
         submission_data: &SubmissionParameters,
     ) -> impl Future<Item = SubmitNonceResponse, Error = FetchError> {
-        let empty = "".to_owned();
         let secret_phrase = self
             .account_id_to_secret_phrase
-            .get(&submission_data.account_id).unwrap_or(&empty);
+            .get(&submission_data.account_id);
 
         let mut query = format!(
-            "requestType=submitNonce&accountId={}&nonce={}&secretPhrase={}&blockheight={}",
-            submission_data.account_id, submission_data.nonce, secret_phrase, submission_data.height
+            "requestType=submitNonce&accountId={}&nonce={}&blockheight={}",
+            submission_data.account_id, submission_data.nonce, submission_data.height
         );
 
-        // If we don't have a secret phrase then we most likely talk to a pool or a proxy.
-        // Both can make use of the deadline, e.g. a proxy won't validate deadlines but still
-        // needs to rank the deadlines.
-        // The best thing is that legacy proxies use the unadjusted deadlines so...
-        // yay another parameter!
-        if secret_phrase == "" {
-            query += &format!("&deadline={}", submission_data.deadline_unadjusted);
-        }        
+        // The secret phrase goes in the body, urls end up in logs of proxies, servers and
+        // our own error messages. Some wallets only read the query though.
+        let mut body = String::new();
+        match secret_phrase {
+            Some(secret_phrase) if self.secret_phrase_in_query => {
+                query += &format!("&secretPhrase={}", secret_phrase.expose());
+            }
+            Some(secret_phrase) => body = format!("secretPhrase={}", secret_phrase.expose()),
+            // If we don't have a secret phrase then we most likely talk to a pool or a proxy.
+            // Both can make use of the deadline, e.g. a proxy won't validate deadlines but still
+            // needs to rank the deadlines.
+            // The best thing is that legacy proxies use the unadjusted deadlines so...
+            // yay another parameter!
+            None => query += &format!("&deadline={}", submission_data.deadline_unadjusted),
+        }
 
         // Some "Extrawurst" for the CreepMiner proxy (I think?) which needs the deadline inside
         // the "X-Deadline" header.
-        let mut headers = (*self.headers).clone();
+        let mut headers = self.headers();
         headers.insert(
             "X-Deadline",
             submission_data.deadline.to_string().parse().unwrap(),
@@ -211,6 +252,8 @@ impl Client {
         self.inner
             .post(uri)
             .headers(headers)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
             .send()
             .and_then(|mut res| {
                 let body = mem::replace(res.body_mut(), Decoder::empty());
@@ -227,10 +270,9 @@ impl Client {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::com::mock::{MockReply, MockServer};
     use tokio;
 
-    static BASE_URL: &str = "https://wallet.burstcoin.ro/";
-
     #[test]
     fn test_submit_params_cmp() {
         let submit_params_1 = SubmissionParameters {
@@ -264,36 +306,121 @@ mod tests {
     #[test]
     fn test_requests() {
         let mut rt = tokio::runtime::Runtime::new().expect("can't create runtime");
+        let server = MockServer::start();
+        server.set_base_target(10);
 
         let mut secret = HashMap::new();
-        secret.insert(1337u64,"secret".to_owned());
+        secret.insert(1337u64, SecretPhrase::new("secret & phrase".to_owned()));
         let client = Client::new(
-            BASE_URL.parse().unwrap(),
-            secret,
+            server.url(),
+            secret.clone(),
+            false,
             5000,
             12,
             ProxyDetails::Enabled,
             HashMap::new(),
         );
 
-        let height = match rt.block_on(client.get_mining_info()) {
+        let mining_info = match rt.block_on(client.get_mining_info()) {
             Err(e) => panic!("can't get mining info: {:?}", e),
-            Ok(mining_info) => mining_info.height,
+            Ok(mining_info) => mining_info,
         };
-
-        // this fails if pinocchio switches to a new block height in the meantime
-        let nonce_submission_response = rt.block_on(client.submit_nonce(&SubmissionParameters {
+        assert_eq!(mining_info.height, 1);
+        assert_eq!(mining_info.base_target, 10);
+        assert_eq!(mining_info.target_deadline, u64::MAX);
+
+        server.new_block();
+        server.set_target_deadline(Some(1000));
+        let mining_info = rt.block_on(client.get_mining_info()).unwrap();
+        assert_eq!(mining_info.height, 2);
+        assert_eq!(mining_info.target_deadline, 1000);
+
+        // scripted block changes every second request
+        server.set_block_interval(2);
+        let mining_info = rt.block_on(client.get_mining_info()).unwrap();
+        assert_eq!(mining_info.height, 2);
+        let mining_info = rt.block_on(client.get_mining_info()).unwrap();
+        assert_eq!(mining_info.height, 3);
+        assert_eq!(server.height(), 3);
+        server.set_block_interval(0);
+
+        let mut params = SubmissionParameters {
             account_id: 1337,
             nonce: 12,
-            height,
+            height: mining_info.height,
             block: 1,
             deadline_unadjusted: 7123,
-            deadline: 1193,
+            deadline: 712,
             gen_sig: [0; 32],
-        }));
+        };
+        let res = rt.block_on(client.submit_nonce(&params));
+        match res {
+            Ok(res) => assert_eq!(res.deadline, 712),
+            Err(e) => panic!("can't submit nonce: {:?}", e),
+        }
+
+        // without a secret phrase the deadline goes along for pools and proxies
+        params.account_id = 1;
+        rt.block_on(client.submit_nonce(&params)).unwrap();
+        let submissions = server.submissions();
+        assert_eq!(submissions.len(), 2);
+        assert_eq!(
+            submissions[0].secret_phrase,
+            Some("secret & phrase".to_owned())
+        );
+        assert!(!submissions[0].secret_phrase_in_query);
+        assert_eq!(submissions[0].deadline_unadjusted, None);
+        assert_eq!(submissions[0].capacity, Some(12));
+        assert_eq!(submissions[1].secret_phrase, None);
+        assert_eq!(submissions[1].deadline_unadjusted, Some(7123));
+        assert_eq!(submissions[1].deadline, Some(712));
+
+        server.script_replies(vec![
+            MockReply::PoolError(1005, "deadline exceeds target".to_owned()),
+            MockReply::Raw(200, "limit exceeded".to_owned()),
+        ]);
+        match rt.block_on(client.submit_nonce(&params)) {
+            Err(FetchError::Pool(e)) => {
+                assert_eq!(e.code, 1005);
+                assert_eq!(e.message, "deadline exceeds target");
+            }
+            _ => panic!("pool error expected"),
+        }
+        match rt.block_on(client.submit_nonce(&params)) {
+            Err(FetchError::Pool(e)) => assert_eq!(e.message, "limit exceeded"),
+            _ => panic!("pool error expected"),
+        }
 
-        if let Err(e) = nonce_submission_response {
-            assert!(false, "can't submit nonce: {:?}", e);
+        // compatibility with wallets only reading the query
+        let client = Client::new(
+            server.url(),
+            secret,
+            true,
+            5000,
+            12,
+            ProxyDetails::Disabled,
+            HashMap::new(),
+        );
+        params.account_id = 1337;
+        rt.block_on(client.submit_nonce(&params)).unwrap();
+        let submission = server.submissions().pop().unwrap();
+        assert_eq!(submission.secret_phrase, Some("secret & phrase".to_owned()));
+        assert!(submission.secret_phrase_in_query);
+
+        // answers slower than the timeout are http errors
+        let client = Client::new(
+            server.url(),
+            HashMap::new(),
+            false,
+            100,
+            12,
+            ProxyDetails::Disabled,
+            HashMap::new(),
+        );
+        server.set_latency(Duration::from_millis(500));
+        match rt.block_on(client.get_mining_info()) {
+            Err(FetchError::Http(_)) => (),
+            _ => panic!("timeout expected"),
         }
     }
 }
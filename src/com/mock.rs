@@ -0,0 +1,258 @@
+//! An in-process wallet/pool for tests, answering `getMiningInfo` and `submitNonce` on localhost.
+
+use futures::future;
+use futures::{Future, Stream};
+use hyper::header::HeaderMap;
+use hyper::service::service_fn;
+use hyper::{Body, Request, Response, Server, StatusCode};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use tokio::timer::Delay;
+use url::form_urlencoded;
+use url::Url;
+
+/// How the mock answers a submission.
+#[derive(Clone, Debug)]
+pub enum MockReply {
+    /// Accepts the deadline sent along.
+    Accept,
+    /// Answers with a `PoolErrorWrapper` payload.
+    PoolError(i32, String),
+    /// Answers with the given status and body, e.g. to mimic a pool under load.
+    Raw(u16, String),
+}
+
+/// A submission as received by the mock.
+#[derive(Clone, Debug)]
+pub struct MockSubmission {
+    pub account_id: u64,
+    pub nonce: u64,
+    pub height: u64,
+    pub secret_phrase: Option<String>,
+    /// Whether the secret phrase came in the url rather than the body.
+    pub secret_phrase_in_query: bool,
+    pub deadline_unadjusted: Option<u64>,
+    pub deadline: Option<u64>,
+    pub capacity: Option<u64>,
+}
+
+struct MockState {
+    height: u64,
+    base_target: u64,
+    target_deadline: Option<u64>,
+    // the block advances after this many mining info requests, 0 never
+    block_interval: usize,
+    mining_info_requests: usize,
+    replies: VecDeque<MockReply>,
+    latency: Duration,
+    submissions: Vec<MockSubmission>,
+}
+
+impl MockState {
+    fn gensig(&self) -> String {
+        format!("{:064x}", self.height)
+    }
+
+    fn respond(
+        &mut self,
+        query: &HashMap<String, String>,
+        params: &HashMap<String, String>,
+        headers: &HeaderMap,
+    ) -> (u16, String) {
+        let param = |name: &str| params.get(name).and_then(|v| v.parse().ok());
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+        };
+        match params.get("requestType").map(String::as_str) {
+            Some("getMiningInfo") => {
+                self.mining_info_requests += 1;
+                if self.mining_info_requests == self.block_interval {
+                    self.mining_info_requests = 0;
+                    self.height += 1;
+                }
+                let mut mining_info = serde_json::json!({
+                    "generationSignature": self.gensig(),
+                    "baseTarget": self.base_target.to_string(),
+                    "height": self.height.to_string(),
+                });
+                if let Some(target_deadline) = self.target_deadline {
+                    mining_info["targetDeadline"] = target_deadline.into();
+                }
+                (200, mining_info.to_string())
+            }
+            Some("submitNonce") => {
+                let submission = MockSubmission {
+                    account_id: param("accountId").unwrap_or(0),
+                    nonce: param("nonce").unwrap_or(0),
+                    height: param("blockheight").unwrap_or(0),
+                    secret_phrase: params.get("secretPhrase").cloned(),
+                    secret_phrase_in_query: query.contains_key("secretPhrase"),
+                    deadline_unadjusted: param("deadline"),
+                    deadline: header("X-Deadline"),
+                    capacity: header("X-Capacity"),
+                };
+                let deadline = submission
+                    .deadline
+                    .or_else(|| submission.deadline_unadjusted.map(|d| d / self.base_target))
+                    .unwrap_or(0);
+                self.submissions.push(submission);
+                match self.replies.pop_front().unwrap_or(MockReply::Accept) {
+                    MockReply::Accept => (
+                        200,
+                        serde_json::json!({ "result": "success", "deadline": deadline })
+                            .to_string(),
+                    ),
+                    MockReply::PoolError(code, message) => (
+                        200,
+                        serde_json::json!({ "error": { "code": code, "message": message } })
+                            .to_string(),
+                    ),
+                    MockReply::Raw(status, body) => (status, body),
+                }
+            }
+            _ => (404, String::new()),
+        }
+    }
+}
+
+/// Serves a fake chain on a free port of localhost until dropped.
+pub struct MockServer {
+    url: Url,
+    state: Arc<Mutex<MockState>>,
+    rt: Option<Runtime>,
+}
+
+impl MockServer {
+    pub fn start() -> MockServer {
+        let state = Arc::new(Mutex::new(MockState {
+            height: 1,
+            base_target: 1,
+            target_deadline: None,
+            block_interval: 0,
+            mining_info_requests: 0,
+            replies: VecDeque::new(),
+            latency: Duration::from_millis(0),
+            submissions: Vec::new(),
+        }));
+
+        let rt = Runtime::new().expect("can't create runtime");
+        let service_state = state.clone();
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(move || {
+            let state = service_state.clone();
+            service_fn(move |req| handle(&state, req))
+        });
+        let url = format!("http://{}", server.local_addr()).parse().unwrap();
+        rt.executor()
+            .spawn(server.map_err(|e| panic!("mock server failed: {}", e)));
+
+        MockServer {
+            url,
+            state,
+            rt: Some(rt),
+        }
+    }
+
+    pub fn url(&self) -> Url {
+        self.url.clone()
+    }
+
+    pub fn height(&self) -> u64 {
+        self.state.lock().unwrap().height
+    }
+
+    /// Moves the chain to the next block.
+    pub fn new_block(&self) {
+        self.state.lock().unwrap().height += 1;
+    }
+
+    /// Moves the chain to the next block after every `requests` mining info requests.
+    pub fn set_block_interval(&self, requests: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.block_interval = requests;
+        state.mining_info_requests = 0;
+    }
+
+    pub fn set_base_target(&self, base_target: u64) {
+        self.state.lock().unwrap().base_target = base_target;
+    }
+
+    pub fn set_target_deadline(&self, target_deadline: Option<u64>) {
+        self.state.lock().unwrap().target_deadline = target_deadline;
+    }
+
+    /// Delays every answer.
+    pub fn set_latency(&self, latency: Duration) {
+        self.state.lock().unwrap().latency = latency;
+    }
+
+    /// Answers the next submissions in order, the ones after are accepted.
+    pub fn script_replies(&self, replies: Vec<MockReply>) {
+        self.state.lock().unwrap().replies.extend(replies);
+    }
+
+    pub fn submissions(&self) -> Vec<MockSubmission> {
+        self.state.lock().unwrap().submissions.clone()
+    }
+
+    /// Waits until `n` submissions were received or `timeout` passed.
+    pub fn wait_for_submissions(&self, n: usize, timeout: Duration) -> Vec<MockSubmission> {
+        let start = Instant::now();
+        loop {
+            let submissions = self.submissions();
+            if submissions.len() >= n || start.elapsed() > timeout {
+                return submissions;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(rt) = self.rt.take() {
+            rt.shutdown_now().wait().ok();
+        }
+    }
+}
+
+fn handle(
+    state: &Arc<Mutex<MockState>>,
+    req: Request<Body>,
+) -> Box<dyn Future<Item = Response<Body>, Error = hyper::Error> + Send> {
+    let state = state.clone();
+    let (parts, body) = req.into_parts();
+    Box::new(body.concat2().and_then(move |body| {
+        let query: HashMap<String, String> = match parts.uri.query() {
+            Some(query) => form_urlencoded::parse(query.as_bytes())
+                .into_owned()
+                .collect(),
+            None => HashMap::new(),
+        };
+        let mut params = query.clone();
+        params.extend(form_urlencoded::parse(&body).into_owned());
+
+        let mut state = state.lock().unwrap();
+        let (status, body) = if parts.uri.path() == "/burst" {
+            state.respond(&query, &params, &parts.headers)
+        } else {
+            (404, String::new())
+        };
+        let response = Response::builder()
+            .status(StatusCode::from_u16(status).unwrap())
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .unwrap();
+
+        let latency = state.latency;
+        drop(state);
+        if latency == Duration::from_millis(0) {
+            return future::Either::A(future::ok(response));
+        }
+        future::Either::B(Delay::new(Instant::now() + latency).then(move |_| Ok(response)))
+    }))
+}
@@ -1,2 +1,5 @@
 pub(crate) mod api;
 pub(crate) mod client;
+#[cfg(test)]
+pub(crate) mod mock;
+pub(crate) mod upstreams;
@@ -0,0 +1,185 @@
+use crate::com::client::Client;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often upstreams preferred over the active one are checked for recovery.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Consecutive failures of the active upstream before the next one takes over, so that a single
+/// timeout doesn't switch pools.
+const FAILOVER_AFTER: usize = 3;
+
+/// An ordered list of pools/wallets. Requests go to the active upstream, if it fails the next
+/// one takes over until a preferred one is healthy again.
+pub struct Upstreams {
+    clients: Vec<Client>,
+    active: AtomicUsize,
+    // consecutive failures of the active upstream
+    failures: AtomicUsize,
+    last_recheck: Mutex<Instant>,
+}
+
+impl Upstreams {
+    pub fn new(clients: Vec<Client>) -> Upstreams {
+        assert!(!clients.is_empty(), "at least one upstream is needed");
+        Upstreams {
+            clients,
+            active: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            last_recheck: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn clients(&self) -> &[Client] {
+        &self.clients
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    pub fn active_client(&self) -> (usize, &Client) {
+        let active = self.active();
+        (active, &self.clients[active])
+    }
+
+    /// Counts a request to `i` that didn't get an answer, the next upstream takes over after
+    /// `FAILOVER_AFTER` of them in a row if `i` is still the active one.
+    pub fn failed(&self, i: usize) {
+        if self.clients.len() < 2 || i != self.active() {
+            return;
+        }
+        if self.failures.fetch_add(1, Ordering::SeqCst) + 1 < FAILOVER_AFTER {
+            return;
+        }
+        let next = (i + 1) % self.clients.len();
+        if self
+            .active
+            .compare_exchange(i, next, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            self.failures.store(0, Ordering::SeqCst);
+            warn!(
+                "{: <80}",
+                format!(
+                    "upstream unreachable, switching: from={}, to={}",
+                    self.clients[i].base_uri(),
+                    self.clients[next].base_uri()
+                )
+            );
+        }
+    }
+
+    /// Counts an answer of `i`, errors reported by the pool included.
+    pub fn answered(&self, i: usize) {
+        if i == self.active() {
+            self.failures.store(0, Ordering::SeqCst);
+        }
+    }
+
+    /// Switches back to `i` if it is preferred over the active upstream.
+    pub fn recovered(&self, i: usize) {
+        let mut active = self.active();
+        while i < active {
+            match self
+                .active
+                .compare_exchange(active, i, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => {
+                    self.failures.store(0, Ordering::SeqCst);
+                    info!(
+                        "{: <80}",
+                        format!(
+                            "upstream recovered, switching: from={}, to={}",
+                            self.clients[active].base_uri(),
+                            self.clients[i].base_uri()
+                        )
+                    );
+                    return;
+                }
+                Err(prev) => active = prev,
+            }
+        }
+    }
+
+    /// Returns the upstreams preferred over the active one if it is time to check on them.
+    pub fn due_for_recheck(&self) -> Vec<usize> {
+        let active = self.active();
+        if active == 0 {
+            return Vec::new();
+        }
+        let mut last_recheck = self.last_recheck.lock().unwrap();
+        if last_recheck.elapsed() < RECHECK_INTERVAL {
+            return Vec::new();
+        }
+        *last_recheck = Instant::now();
+        (0..active).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::com::client::ProxyDetails;
+    use std::collections::HashMap;
+
+    fn new_upstreams(n: usize) -> Upstreams {
+        let clients = (0..n)
+            .map(|i| {
+                Client::new(
+                    format!("http://127.0.0.{}:8125", i + 1).parse().unwrap(),
+                    HashMap::new(),
+                    false,
+                    1000,
+                    0,
+                    ProxyDetails::Disabled,
+                    HashMap::new(),
+                )
+            })
+            .collect();
+        Upstreams::new(clients)
+    }
+
+    fn fail(upstreams: &Upstreams, i: usize) {
+        for _ in 0..FAILOVER_AFTER {
+            upstreams.failed(i);
+        }
+    }
+
+    #[test]
+    fn test_failover() {
+        let upstreams = new_upstreams(3);
+        assert_eq!(upstreams.active(), 0);
+        assert!(upstreams.due_for_recheck().is_empty());
+
+        // only consecutive failures count
+        for _ in 0..FAILOVER_AFTER - 1 {
+            upstreams.failed(0);
+        }
+        upstreams.answered(0);
+        upstreams.failed(0);
+        assert_eq!(upstreams.active(), 0);
+
+        fail(&upstreams, 0);
+        assert_eq!(upstreams.active(), 1);
+        // a late failure of an upstream that isn't active anymore changes nothing
+        fail(&upstreams, 0);
+        assert_eq!(upstreams.active(), 1);
+        fail(&upstreams, 1);
+        assert_eq!(upstreams.active(), 2);
+
+        // only preferred upstreams take over again
+        upstreams.recovered(2);
+        assert_eq!(upstreams.active(), 2);
+        upstreams.recovered(1);
+        assert_eq!(upstreams.active(), 1);
+
+        fail(&upstreams, 1);
+        fail(&upstreams, 2);
+        assert_eq!(upstreams.active(), 0);
+
+        let upstreams = new_upstreams(1);
+        fail(&upstreams, 0);
+        assert_eq!(upstreams.active(), 0);
+    }
+}
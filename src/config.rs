@@ -1,7 +1,11 @@
-use serde::de::{Deserialize, Deserializer};
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
 use std::collections::HashMap;
+use std::env;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::u32;
 use url::Url;
 
@@ -14,23 +18,59 @@ pub enum Benchmark {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Cfg {
-    #[serde(default = "default_secret_phrase")]
-    pub account_id_to_secret_phrase: HashMap<u64, String>,
+    #[serde(default = "default_secret_phrase", with = "account_map")]
+    pub account_id_to_secret_phrase: HashMap<u64, SecretPhrase>,
+
+    #[serde(default = "default_secret_phrase_in_query")]
+    pub secret_phrase_in_query: bool,
+
+    #[serde(default = "default_keystore_file")]
+    pub keystore_file: Option<PathBuf>,
 
     pub plot_dirs: Vec<PathBuf>,
 
+    #[serde(default = "default_rescan_plot_dirs")]
+    pub rescan_plot_dirs: bool,
+
+    #[serde(default = "default_plot_index_file")]
+    pub plot_index_file: Option<PathBuf>,
+
     #[serde(with = "url_serde")]
     pub url: Url,
 
+    #[serde(default = "default_fallback_urls", with = "url_list")]
+    pub fallback_urls: Vec<Url>,
+
+    #[serde(default = "default_account_id_to_upstream", with = "account_map")]
+    pub account_id_to_upstream: HashMap<u64, AccountUpstream>,
+
+    #[serde(default = "default_chains")]
+    pub chains: Vec<Chain>,
+
+    #[serde(default = "default_proxy_listen_address")]
+    pub proxy_listen_address: Option<SocketAddr>,
+
+    #[serde(default = "default_status_listen_address")]
+    pub status_listen_address: Option<SocketAddr>,
+
+    #[serde(default = "default_history_dir")]
+    pub history_dir: Option<PathBuf>,
+
     #[serde(default = "default_hdd_reader_thread_count")]
     pub hdd_reader_thread_count: usize,
 
+    #[serde(default = "default_hdd_reader_tasks_per_drive")]
+    pub hdd_reader_tasks_per_drive: usize,
+
     #[serde(default = "default_hdd_use_direct_io")]
     pub hdd_use_direct_io: bool,
 
     #[serde(default = "default_hdd_wakeup_after")]
     pub hdd_wakeup_after: i64,
 
+    #[serde(default = "default_hdd_io_uring_queue_depth")]
+    pub hdd_io_uring_queue_depth: usize,
+
     #[serde(default = "default_cpu_threads")]
     pub cpu_threads: usize,
 
@@ -67,7 +107,10 @@ pub struct Cfg {
     #[serde(default = "default_target_deadline")]
     pub target_deadline: u64,
 
-    #[serde(default = "default_account_id_to_target_deadline")]
+    #[serde(
+        default = "default_account_id_to_target_deadline",
+        with = "account_map"
+    )]
     pub account_id_to_target_deadline: HashMap<u64, u64>,
 
     #[serde(default = "default_get_mining_info_interval")]
@@ -100,15 +143,132 @@ pub struct Cfg {
     #[serde(default = "default_logfile_log_pattern")]
     pub logfile_log_pattern: String,
 
+    #[serde(default = "default_log_format")]
+    pub console_log_format: String,
+
+    #[serde(default = "default_log_format")]
+    pub logfile_log_format: String,
+
     #[serde(default = "default_show_progress")]
     pub show_progress: bool,
 
     #[serde(default = "default_show_drive_stats")]
     pub show_drive_stats: bool,
 
+    #[serde(default = "default_show_dashboard")]
+    pub show_dashboard: bool,
+
+    #[serde(default = "default_show_rs_addresses")]
+    pub show_rs_addresses: bool,
+
+    #[serde(default = "default_account_aliases", with = "account_map")]
+    pub account_aliases: HashMap<u64, String>,
+
     pub benchmark_only: Option<Benchmark>,
 }
 
+/// A pool or wallet deadlines of a single account are submitted to instead of `url`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AccountUpstream {
+    #[serde(with = "url_serde")]
+    pub url: Url,
+
+    #[serde(default = "default_additional_headers")]
+    pub additional_headers: HashMap<String, String>,
+
+    /// Overrides the account's entry in `account_id_to_secret_phrase`.
+    #[serde(default)]
+    pub secret_phrase: Option<SecretPhrase>,
+}
+
+/// A passphrase, it never shows up in `Debug` output or serialized configs.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecretPhrase(String);
+
+impl SecretPhrase {
+    pub fn new(phrase: String) -> SecretPhrase {
+        SecretPhrase(phrase)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretPhrase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretPhrase(***)")
+    }
+}
+
+impl Serialize for SecretPhrase {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str("***")
+    }
+}
+
+// a passphrase is given inline or taken from an environment variable or a file, so that configs
+// referencing them can be shared
+impl<'de> Deserialize<'de> for SecretPhrase {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Source {
+            Inline(String),
+            Env { env: String },
+            File { file: PathBuf },
+        }
+
+        match Source::deserialize(deserializer)? {
+            Source::Inline(secret_phrase) => Ok(SecretPhrase(secret_phrase)),
+            Source::Env { env } => env::var(&env).map(SecretPhrase).map_err(|e| {
+                de::Error::custom(format!("can't read secret phrase from ${}: {}", env, e))
+            }),
+            Source::File { file } => read_secret_file(&file)
+                .map(SecretPhrase)
+                .map_err(de::Error::custom),
+        }
+    }
+}
+
+fn read_secret_file(path: &Path) -> Result<String, String> {
+    let error = |e| format!("can't read secret phrase from {}: {}", path.display(), e);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path).map_err(error)?.permissions().mode();
+        if mode & 0o077 != 0 {
+            return Err(format!(
+                "{} is accessible by other users, restrict it with chmod 600",
+                path.display()
+            ));
+        }
+    }
+    let secret_phrase = fs::read_to_string(path).map_err(error)?;
+    Ok(secret_phrase.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
+
+/// A further chain mined with the same plots, it gets its mining info from and submits to `url`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Chain {
+    pub name: String,
+
+    #[serde(with = "url_serde")]
+    pub url: Url,
+
+    #[serde(default = "default_fallback_urls", with = "url_list")]
+    pub fallback_urls: Vec<Url>,
+
+    #[serde(default = "default_additional_headers")]
+    pub additional_headers: HashMap<String, String>,
+}
+
 impl<'de> Deserialize<'de> for Benchmark {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -123,14 +283,128 @@ impl<'de> Deserialize<'de> for Benchmark {
     }
 }
 
-fn default_secret_phrase() -> HashMap<u64, String> {
+// url_serde only handles single urls
+mod url_list {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use url::Url;
+
+    pub fn serialize<S: Serializer>(urls: &[Url], serializer: S) -> Result<S::Ok, S::Error> {
+        let urls: Vec<url_serde::Ser<Url>> = urls.iter().map(url_serde::Ser::new).collect();
+        urls.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Url>, D::Error> {
+        let urls: Vec<url_serde::De<Url>> = Vec::deserialize(deserializer)?;
+        Ok(urls.into_iter().map(url_serde::De::into_inner).collect())
+    }
+}
+
+// account ids can be numeric or Reed-Solomon addresses
+mod account_map {
+    use crate::account::parse_account_id;
+    use serde::de::{self, Deserialize, Deserializer, Visitor};
+    use serde::{Serialize, Serializer};
+    use std::collections::HashMap;
+    use std::fmt;
+
+    #[derive(PartialEq, Eq, Hash)]
+    struct AccountId(u64);
+
+    impl<'de> Deserialize<'de> for AccountId {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct AccountIdVisitor;
+
+            impl<'de> Visitor<'de> for AccountIdVisitor {
+                type Value = AccountId;
+
+                fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                    formatter.write_str("numeric account id or BURST address")
+                }
+
+                fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                    Ok(AccountId(v))
+                }
+
+                fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                    parse_account_id(v)
+                        .map(AccountId)
+                        .map_err(de::Error::custom)
+                }
+            }
+
+            deserializer.deserialize_any(AccountIdVisitor)
+        }
+    }
+
+    pub fn serialize<S: Serializer, V: Serialize>(
+        map: &HashMap<u64, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        map.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D, V>(deserializer: D) -> Result<HashMap<u64, V>, D::Error>
+    where
+        D: Deserializer<'de>,
+        V: Deserialize<'de>,
+    {
+        let map: HashMap<AccountId, V> = HashMap::deserialize(deserializer)?;
+        Ok(map.into_iter().map(|(id, v)| (id.0, v)).collect())
+    }
+}
+
+fn default_secret_phrase() -> HashMap<u64, SecretPhrase> {
     HashMap::new()
 }
 
+fn default_secret_phrase_in_query() -> bool {
+    false
+}
+
+fn default_keystore_file() -> Option<PathBuf> {
+    None
+}
+
+fn default_fallback_urls() -> Vec<Url> {
+    Vec::new()
+}
+
+fn default_account_id_to_upstream() -> HashMap<u64, AccountUpstream> {
+    HashMap::new()
+}
+
+fn default_chains() -> Vec<Chain> {
+    Vec::new()
+}
+
+fn default_proxy_listen_address() -> Option<SocketAddr> {
+    None
+}
+
+fn default_status_listen_address() -> Option<SocketAddr> {
+    None
+}
+
+fn default_history_dir() -> Option<PathBuf> {
+    Some(PathBuf::from("history"))
+}
+
+fn default_rescan_plot_dirs() -> bool {
+    true
+}
+
+fn default_plot_index_file() -> Option<PathBuf> {
+    Some(PathBuf::from("plot_index.json"))
+}
+
 fn default_hdd_reader_thread_count() -> usize {
     0
 }
 
+fn default_hdd_reader_tasks_per_drive() -> usize {
+    0
+}
+
 fn default_hdd_use_direct_io() -> bool {
     true
 }
@@ -139,6 +413,10 @@ fn default_hdd_wakeup_after() -> i64 {
     240
 }
 
+fn default_hdd_io_uring_queue_depth() -> usize {
+    0
+}
+
 fn default_cpu_threads() -> usize {
     0
 }
@@ -231,6 +509,10 @@ fn default_logfile_log_pattern() -> String {
     "\r{d(%Y-%m-%dT%H:%M:%S.%3f%z)} [{h({l}):<5}] [{T}] [{f}:{L}] [{t}] - {M}:{m}{n}".to_owned()
 }
 
+fn default_log_format() -> String {
+    "text".to_owned()
+}
+
 fn default_show_progress() -> bool {
     true
 }
@@ -239,6 +521,18 @@ fn default_show_drive_stats() -> bool {
     false
 }
 
+fn default_show_dashboard() -> bool {
+    false
+}
+
+fn default_show_rs_addresses() -> bool {
+    false
+}
+
+fn default_account_aliases() -> HashMap<u64, String> {
+    HashMap::new()
+}
+
 pub fn load_cfg(config: &str) -> Cfg {
     let cfg_str = fs::read_to_string(config).expect("failed to open config");
     let cfg: Cfg = serde_yaml::from_str(&cfg_str).expect("failed to parse config");
@@ -280,6 +574,17 @@ pub fn validate_cfg(mut cfg: Cfg) -> Cfg {
         })
         .collect();
 
+    if cfg.hdd_io_uring_queue_depth > 0 && !cfg!(all(feature = "io_uring", target_os = "linux")) {
+        warn!("hdd_io_uring_queue_depth is set but io_uring support isn't built in, ignoring it");
+        cfg.hdd_io_uring_queue_depth = 0;
+    }
+
+    // the async gpu worker hashes a buffer with the gensig of the one before
+    if !cfg.chains.is_empty() && cfg.gpu_async {
+        warn!("gpu_async can't be used when mining several chains, ignoring it");
+        cfg.gpu_async = false;
+    }
+
     cfg
 }
 
@@ -318,5 +623,128 @@ mod tests {
         let mut pb = PathBuf::new();
         pb.push("test_data");
         assert_eq!(cfg.plot_dirs, vec![pb]);
+        assert!(cfg.rescan_plot_dirs);
+        assert!(cfg.fallback_urls.is_empty());
+
+        let cfg: Cfg = serde_yaml::from_str(
+            "plot_dirs: []\n\
+             url: 'http://pool.one:8080'\n\
+             fallback_urls: ['http://pool.two:8080', 'http://127.0.0.1:8125']",
+        )
+        .unwrap();
+        assert_eq!(cfg.fallback_urls.len(), 2);
+        assert!(!cfg.secret_phrase_in_query);
+        assert_eq!(cfg.fallback_urls[1].port(), Some(8125));
+        assert!(cfg.account_id_to_upstream.is_empty());
+
+        let cfg: Cfg = serde_yaml::from_str(
+            "plot_dirs: []\n\
+             url: 'http://pool.one:8080'\n\
+             account_id_to_upstream:\n  \
+               1: {url: 'http://127.0.0.1:8125', secret_phrase: 'secret'}\n  \
+               2: {url: 'http://pool.two:8080', additional_headers: {AccountKey: '1234'}}",
+        )
+        .unwrap();
+        let solo = &cfg.account_id_to_upstream[&1];
+        assert_eq!(solo.url.port(), Some(8125));
+        assert_eq!(
+            solo.secret_phrase,
+            Some(SecretPhrase::new("secret".to_owned()))
+        );
+        let pool = &cfg.account_id_to_upstream[&2];
+        assert_eq!(pool.secret_phrase, None);
+        assert_eq!(pool.additional_headers["AccountKey"], "1234");
+        assert!(cfg.chains.is_empty());
+
+        let cfg: Cfg = serde_yaml::from_str(
+            "plot_dirs: []\n\
+             url: 'http://pool.one:8080'\n\
+             chains:\n  \
+               - {name: 'fork', url: 'http://127.0.0.1:9125', fallback_urls: ['http://pool.two:8080']}",
+        )
+        .unwrap();
+        assert_eq!(cfg.chains.len(), 1);
+        assert_eq!(cfg.chains[0].name, "fork");
+        assert_eq!(cfg.chains[0].url.port(), Some(9125));
+        assert_eq!(cfg.chains[0].fallback_urls.len(), 1);
+        assert_eq!(cfg.proxy_listen_address, None);
+        assert_eq!(cfg.status_listen_address, None);
+        assert_eq!(cfg.history_dir, Some(PathBuf::from("history")));
+        assert_eq!(cfg.hdd_io_uring_queue_depth, 0);
+
+        // accounts are given as ids or addresses
+        let cfg: Cfg = serde_yaml::from_str(
+            "plot_dirs: []\n\
+             url: 'http://127.0.0.1:8125'\n\
+             account_id_to_target_deadline: {BURST-VGV3-LD3D-WU5E-A5LEX: 100, 2: 200}\n\
+             account_aliases: {burst-fnt5-ty8z-78tf-3mnvk: 'johnny'}",
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.account_id_to_target_deadline[&10282355196851764065],
+            100
+        );
+        assert_eq!(cfg.account_id_to_target_deadline[&2], 200);
+        assert_eq!(cfg.account_aliases[&1796535821016683299], "johnny");
+        assert!(!cfg.show_rs_addresses);
+        assert!(!cfg.show_dashboard);
+    }
+
+    #[test]
+    fn test_secret_phrase_sources() {
+        let dir = env::temp_dir().join(format!("scavenger_secrets_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("secret");
+        fs::write(&file, "from file\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        env::set_var("SCAVENGER_TEST_SECRET", "from env");
+
+        let cfg: Cfg = serde_yaml::from_str(&format!(
+            "plot_dirs: []\n\
+             url: 'http://127.0.0.1:8125'\n\
+             account_id_to_secret_phrase:\n  \
+               1: 'inline'\n  \
+               2: {{env: 'SCAVENGER_TEST_SECRET'}}\n  \
+               3: {{file: '{}'}}",
+            file.display()
+        ))
+        .unwrap();
+        assert_eq!(cfg.account_id_to_secret_phrase[&1].expose(), "inline");
+        assert_eq!(cfg.account_id_to_secret_phrase[&2].expose(), "from env");
+        assert_eq!(cfg.account_id_to_secret_phrase[&3].expose(), "from file");
+        assert_eq!(
+            serde_json::to_string(&cfg.account_id_to_secret_phrase[&1]).unwrap(),
+            "\"***\""
+        );
+        assert!(!serde_json::to_string(&cfg).unwrap().contains("inline"));
+        assert_eq!(cfg.keystore_file, None);
+
+        let missing: Result<Cfg, _> = serde_yaml::from_str(
+            "plot_dirs: []\n\
+             url: 'http://127.0.0.1:8125'\n\
+             account_id_to_secret_phrase: {1: {env: 'SCAVENGER_TEST_SECRET_MISSING'}}",
+        );
+        assert!(missing.is_err());
+
+        // files readable by others are refused
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(read_secret_file(&file).is_err());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_debug_hides_secret_phrases() {
+        let cfg = load_cfg("config.yaml");
+        let secret_phrase = cfg.account_id_to_secret_phrase[&10282355196851764065].expose();
+        assert!(secret_phrase.starts_with("glad suffer"));
+        assert!(!format!("{:?}", cfg).contains(secret_phrase));
     }
 }
@@ -0,0 +1,466 @@
+use crate::account::Account;
+use crate::plot::{
+    parse_file_name, poc1_addr, Plot, NONCE_SIZE, SCOOPS_IN_NONCE, SCOOP_SIZE, SHABAL256_HASH_SIZE,
+};
+use pbr::{ProgressBar, Units};
+use std::cmp::min;
+use std::error::Error;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// scoop i and scoop 4095 - i exchange their second hashes, so they are converted as a pair
+const SCOOP_PAIRS: u64 = SCOOPS_IN_NONCE / 2;
+// nonces of a scoop pair converted at once, 8 MiB of buffer
+const WINDOW_NONCES: u64 = 1 << 16;
+
+pub struct Converter {
+    src: PathBuf,
+    dst: PathBuf,
+    journal: PathBuf,
+    account_id: u64,
+    nonces: u64,
+    stagger: u64,
+    in_place: bool,
+    window_nonces: u64,
+    show_progress: bool,
+}
+
+impl Converter {
+    /// Converts the PoC1 plot at `path` into a PoC2 plot in `out_dir`, or in place if no
+    /// output directory is given.
+    pub fn new(
+        path: &Path,
+        out_dir: Option<&Path>,
+        show_progress: bool,
+    ) -> Result<Converter, Box<dyn Error>> {
+        let (account_id, start_nonce, nonces, stagger) = parse_file_name(path)?;
+        let stagger = match stagger {
+            Some(stagger) => stagger,
+            None => {
+                return Err(From::from(format!(
+                    "{} is not a PoC1 plot",
+                    path.to_str().unwrap()
+                )))
+            }
+        };
+
+        let in_place = out_dir.is_none();
+        if in_place && stagger != nonces {
+            return Err(From::from(format!(
+                "in place conversion needs stagger = nonces ({} != {}), convert into another \
+                 directory instead",
+                stagger, nonces
+            )));
+        }
+        let dir = match out_dir {
+            Some(dir) => dir,
+            None => path.parent().unwrap(),
+        };
+        if !dir.is_dir() {
+            return Err(From::from(format!(
+                "{} is not a directory",
+                dir.to_str().unwrap()
+            )));
+        }
+
+        let dst = dir.join(format!("{}_{}_{}", account_id, start_nonce, nonces));
+        if dst.exists() {
+            return Err(From::from(format!(
+                "{} already exists",
+                dst.to_str().unwrap()
+            )));
+        }
+
+        // an interrupted in place conversion has moved the plot to its temporary name already
+        let tmp_path = dst.with_extension("tmp");
+        if in_place && !path.exists() && tmp_path.is_file() {
+            let size = fs::metadata(&tmp_path)?.len();
+            if size != nonces * NONCE_SIZE {
+                return Err(From::from(format!(
+                    "expected plot size {} but got {}",
+                    nonces * NONCE_SIZE,
+                    size
+                )));
+            }
+        } else {
+            Plot::new(&path.to_path_buf(), false, false)?;
+        }
+
+        Ok(Converter {
+            src: path.to_path_buf(),
+            journal: dst.with_extension("convert"),
+            dst,
+            account_id,
+            nonces,
+            stagger,
+            in_place,
+            window_nonces: WINDOW_NONCES,
+            show_progress,
+        })
+    }
+
+    /// Converts scoop pair by scoop pair, a window of nonces at a time. The plot is converted
+    /// under a temporary name that miners ignore, so that they don't mine a half converted
+    /// plot. Progress is kept in a journal next to the target so that an interrupted
+    /// conversion resumes where it stopped. In place conversions also journal the converted
+    /// window before overwriting it, a half written window could not be recovered otherwise.
+    pub fn run(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let tmp_path = self.dst.with_extension("tmp");
+        let (mut src, mut dst) = if self.in_place {
+            if self.src.exists() {
+                fs::rename(&self.src, &tmp_path)?;
+            }
+            (
+                File::open(&tmp_path)?,
+                OpenOptions::new().read(true).write(true).open(&tmp_path)?,
+            )
+        } else {
+            let fh = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&tmp_path)?;
+            fh.set_len(self.nonces * NONCE_SIZE)?;
+            (File::open(&self.src)?, fh)
+        };
+
+        let (first_pair, first_nonce) = self.resume(&mut dst)?;
+        info!(
+            "converting: file={}, target={}, account={}, nonces={}, stagger={}, in_place={}",
+            self.src.to_str().unwrap(),
+            self.dst.to_str().unwrap(),
+            Account(self.account_id),
+            self.nonces,
+            self.stagger,
+            self.in_place
+        );
+        if (first_pair, first_nonce) != (0, 0) {
+            info!(
+                "converting: resuming at scoop pair {}, nonce {}",
+                first_pair, first_nonce
+            );
+        }
+
+        let mut pb = if self.show_progress {
+            let mut pb = ProgressBar::new(self.nonces * NONCE_SIZE);
+            pb.format("│██░│");
+            pb.set_width(Some(80));
+            pb.set_units(Units::Bytes);
+            pb.message("Converting: ");
+            pb.add((first_pair * self.nonces + first_nonce) * 2 * SCOOP_SIZE);
+            Some(pb)
+        } else {
+            None
+        };
+
+        let mut bs = vec![0u8; (2 * min(self.window_nonces, self.nonces) * SCOOP_SIZE) as usize];
+        let mut start_nonce = first_nonce;
+        for pair in first_pair..SCOOP_PAIRS {
+            while start_nonce < self.nonces {
+                let window_nonces = min(self.window_nonces, self.nonces - start_nonce);
+                let bs = &mut bs[..(2 * window_nonces * SCOOP_SIZE) as usize];
+                self.convert_window(&mut src, pair, start_nonce, bs)?;
+                if self.in_place {
+                    self.write_journal(pair, start_nonce, bs)?;
+                    self.write_window(&mut dst, pair, start_nonce, bs)?;
+                } else {
+                    self.write_window(&mut dst, pair, start_nonce, bs)?;
+                    self.write_journal(pair, start_nonce, &[])?;
+                }
+                if let Some(pb) = &mut pb {
+                    pb.add(2 * window_nonces * SCOOP_SIZE);
+                }
+                start_nonce += window_nonces;
+            }
+            start_nonce = 0;
+        }
+        if let Some(pb) = &mut pb {
+            pb.finish_print("");
+        }
+
+        fs::rename(&tmp_path, &self.dst)?;
+        fs::remove_file(&self.journal)?;
+        info!("conversion finished: file={}", self.dst.to_str().unwrap());
+        Ok(self.dst.clone())
+    }
+
+    // returns the scoop pair and nonce the conversion continues with
+    fn resume(&self, dst: &mut File) -> io::Result<(u64, u64)> {
+        let journal = match fs::read(&self.journal) {
+            Ok(journal) => journal,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok((0, 0)),
+            Err(e) => return Err(e),
+        };
+        let corrupted = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("corrupted journal {}", self.journal.to_str().unwrap()),
+            )
+        };
+        if journal.len() < 16 {
+            return Err(corrupted());
+        }
+
+        let mut pair = [0u8; 8];
+        pair.copy_from_slice(&journal[0..8]);
+        let pair = u64::from_le_bytes(pair);
+        let mut start_nonce = [0u8; 8];
+        start_nonce.copy_from_slice(&journal[8..16]);
+        let start_nonce = u64::from_le_bytes(start_nonce);
+        if pair >= SCOOP_PAIRS || start_nonce >= self.nonces {
+            return Err(corrupted());
+        }
+        let window_nonces = min(self.window_nonces, self.nonces - start_nonce);
+        if self.in_place {
+            let data = &journal[16..];
+            if data.len() as u64 != 2 * window_nonces * SCOOP_SIZE {
+                return Err(corrupted());
+            }
+            // the window might have been written partially
+            self.write_window(dst, pair, start_nonce, data)?;
+        }
+        if start_nonce + window_nonces < self.nonces {
+            Ok((pair, start_nonce + window_nonces))
+        } else {
+            Ok((pair + 1, 0))
+        }
+    }
+
+    // reads a window of scoop `pair` and its mirror scoop into `bs` and exchanges their second
+    // hashes, the window starts at `start_nonce` and fills `bs`
+    fn convert_window(
+        &self,
+        src: &mut File,
+        pair: u64,
+        start_nonce: u64,
+        bs: &mut [u8],
+    ) -> io::Result<()> {
+        let (scoop, mirror) = bs.split_at_mut(bs.len() / 2);
+        self.read_scoop(src, pair as u32, start_nonce, scoop)?;
+        self.read_scoop(
+            src,
+            (SCOOPS_IN_NONCE - pair - 1) as u32,
+            start_nonce,
+            mirror,
+        )?;
+
+        let hash_size = SHABAL256_HASH_SIZE as usize;
+        for (a, b) in scoop
+            .chunks_mut(SCOOP_SIZE as usize)
+            .zip(mirror.chunks_mut(SCOOP_SIZE as usize))
+        {
+            a[hash_size..].swap_with_slice(&mut b[hash_size..]);
+        }
+        Ok(())
+    }
+
+    // PoC1 plots keep the scoops of a stagger group together, windows can span groups
+    fn read_scoop(
+        &self,
+        src: &mut File,
+        scoop: u32,
+        start_nonce: u64,
+        bs: &mut [u8],
+    ) -> io::Result<()> {
+        let mut nonce = start_nonce;
+        let mut filled = 0;
+        while filled < bs.len() {
+            let group_end = (nonce / self.stagger + 1) * self.stagger;
+            let len = min((group_end - nonce) * SCOOP_SIZE, (bs.len() - filled) as u64) as usize;
+            src.seek(SeekFrom::Start(poc1_addr(scoop, nonce, self.stagger)))?;
+            src.read_exact(&mut bs[filled..filled + len])?;
+            filled += len;
+            nonce += len as u64 / SCOOP_SIZE;
+        }
+        Ok(())
+    }
+
+    fn write_window(
+        &self,
+        dst: &mut File,
+        pair: u64,
+        start_nonce: u64,
+        bs: &[u8],
+    ) -> io::Result<()> {
+        let scoop_size = self.nonces * SCOOP_SIZE;
+        let offset = start_nonce * SCOOP_SIZE;
+        let (scoop, mirror) = bs.split_at(bs.len() / 2);
+        dst.seek(SeekFrom::Start(pair * scoop_size + offset))?;
+        dst.write_all(scoop)?;
+        dst.seek(SeekFrom::Start(
+            (SCOOPS_IN_NONCE - pair - 1) * scoop_size + offset,
+        ))?;
+        dst.write_all(mirror)?;
+        dst.sync_data()
+    }
+
+    // the journal is replaced atomically, so it is always complete
+    fn write_journal(&self, pair: u64, start_nonce: u64, bs: &[u8]) -> io::Result<()> {
+        let tmp_path = self.journal.with_extension("convert.tmp");
+        let mut journal = File::create(&tmp_path)?;
+        journal.write_all(&pair.to_le_bytes())?;
+        journal.write_all(&start_nonce.to_le_bytes())?;
+        journal.write_all(bs)?;
+        journal.sync_all()?;
+        fs::rename(&tmp_path, &self.journal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    // rearranges the PoC2 test plot into a PoC1 plot with the given stagger
+    fn write_poc1_plot(dir: &Path, stagger: u64) -> PathBuf {
+        let poc2 = fs::read("test_data/10282355196851764065_0_8").unwrap();
+        let nonces = 8;
+        let scoop_size = SCOOP_SIZE as usize;
+        let hash_size = SHABAL256_HASH_SIZE as usize;
+        let mut poc1 = vec![0u8; poc2.len()];
+        for scoop in 0..SCOOPS_IN_NONCE as u32 {
+            let mirror = SCOOPS_IN_NONCE as u32 - scoop - 1;
+            for n in 0..nonces {
+                let dst = poc1_addr(scoop, n, stagger) as usize;
+                let src = (u64::from(scoop) * nonces + n) as usize * scoop_size;
+                let src_mirror = (u64::from(mirror) * nonces + n) as usize * scoop_size;
+                poc1[dst..dst + hash_size].copy_from_slice(&poc2[src..src + hash_size]);
+                poc1[dst + hash_size..dst + scoop_size]
+                    .copy_from_slice(&poc2[src_mirror + hash_size..src_mirror + scoop_size]);
+            }
+        }
+        let path = dir.join(format!("10282355196851764065_0_8_{}", stagger));
+        fs::write(&path, &poc1).unwrap();
+        path
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_read_poc1() {
+        let dir = test_dir("scavenger_test_read_poc1");
+        let expected = fs::read("test_data/10282355196851764065_0_8").unwrap();
+        for &stagger in &[2, 8] {
+            let mut plot = Plot::new(&write_poc1_plot(&dir, stagger), true, false).unwrap();
+            for &scoop in &[0u32, 1, 2047, 2048, 4095] {
+                let mut bs = vec![0u8; 3 * SCOOP_SIZE as usize];
+                let mut read = Vec::new();
+                plot.prepare(scoop).unwrap();
+                loop {
+                    let (bytes_read, start_nonce, finished) = plot.read(&mut bs, scoop).unwrap();
+                    assert_eq!(start_nonce, read.len() as u64 / SCOOP_SIZE);
+                    read.extend_from_slice(&bs[..bytes_read]);
+                    if finished {
+                        break;
+                    }
+                }
+                let scoop_size = 8 * SCOOP_SIZE as usize;
+                let offset = scoop as usize * scoop_size;
+                assert_eq!(&read[..], &expected[offset..offset + scoop_size]);
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_convert() {
+        let expected = fs::read("test_data/10282355196851764065_0_8").unwrap();
+
+        let dir = test_dir("scavenger_test_convert");
+        let out_dir = dir.join("out");
+        fs::create_dir_all(&out_dir).unwrap();
+        let src = write_poc1_plot(&dir, 2);
+        assert!(Converter::new(&src, None, false).is_err());
+        let converted = Converter::new(&src, Some(&out_dir), false)
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(converted, out_dir.join("10282355196851764065_0_8"));
+        assert_eq!(fs::read(&converted).unwrap(), expected);
+        assert!(src.exists());
+
+        let src = write_poc1_plot(&dir, 8);
+        let converted = Converter::new(&src, None, false).unwrap().run().unwrap();
+        assert_eq!(converted, dir.join("10282355196851764065_0_8"));
+        assert_eq!(fs::read(&converted).unwrap(), expected);
+        assert!(!src.exists());
+        assert!(!dir.join("10282355196851764065_0_8.convert").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_convert_windows() {
+        let expected = fs::read("test_data/10282355196851764065_0_8").unwrap();
+        let dir = test_dir("scavenger_test_convert_windows");
+        let out_dir = dir.join("out");
+        fs::create_dir_all(&out_dir).unwrap();
+
+        // windows of 3 nonces span the stagger groups of 2 nonces and leave a short last window
+        let src = write_poc1_plot(&dir, 2);
+        let mut converter = Converter::new(&src, Some(&out_dir), false).unwrap();
+        converter.window_nonces = 3;
+        let converted = converter.run().unwrap();
+        assert_eq!(fs::read(&converted).unwrap(), expected);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_convert_resume() {
+        let expected = fs::read("test_data/10282355196851764065_0_8").unwrap();
+        let dir = test_dir("scavenger_test_convert_resume");
+        let src = write_poc1_plot(&dir, 8);
+        let mut converter = Converter::new(&src, None, false).unwrap();
+        converter.window_nonces = 3;
+
+        // interrupt an in place conversion after journaling the second window of pair 100 but
+        // before writing it
+        let tmp_path = dir.join("10282355196851764065_0_8.tmp");
+        fs::rename(&src, &tmp_path).unwrap();
+        let mut src_fh = File::open(&tmp_path).unwrap();
+        let mut dst_fh = OpenOptions::new().write(true).open(&tmp_path).unwrap();
+        let mut bs = vec![0u8; 2 * 3 * SCOOP_SIZE as usize];
+        'convert: for pair in 0..=100 {
+            for &start_nonce in &[0, 3, 6] {
+                let bs = &mut bs[..2 * min(3, 8 - start_nonce) as usize * SCOOP_SIZE as usize];
+                converter
+                    .convert_window(&mut src_fh, pair, start_nonce, bs)
+                    .unwrap();
+                converter.write_journal(pair, start_nonce, bs).unwrap();
+                if pair == 100 && start_nonce == 3 {
+                    break 'convert;
+                }
+                converter
+                    .write_window(&mut dst_fh, pair, start_nonce, bs)
+                    .unwrap();
+            }
+        }
+        // write half of the window only
+        dst_fh
+            .seek(SeekFrom::Start((100 * 8 + 3) * SCOOP_SIZE))
+            .unwrap();
+        dst_fh.write_all(&bs[..3 * SCOOP_SIZE as usize]).unwrap();
+        drop(dst_fh);
+
+        // the plot is hidden from miners while converting
+        assert!(!src.exists());
+        assert!(Plot::new(&tmp_path, false, false).is_err());
+
+        let mut converter = Converter::new(&src, None, false).unwrap();
+        converter.window_nonces = 3;
+        let converted = converter.run().unwrap();
+        assert_eq!(fs::read(&converted).unwrap(), expected);
+        assert!(!tmp_path.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,3 +1,4 @@
+use crate::metrics;
 use crate::miner::{Buffer, NonceData};
 use crate::poc_hashing::find_best_deadline_rust;
 use crate::reader::ReadReply;
@@ -104,6 +105,7 @@ pub fn hash(
                         nonce: 0,
                         reader_task_processed: read_reply.info.finished,
                         account_id: read_reply.info.account_id,
+                        chain: read_reply.info.chain,
                     })
                     .wait()
                     .expect("CPU worker failed to send nonce data");
@@ -206,6 +208,8 @@ pub fn hash(
             deadline = result.0;
             offset = result.1;
         }
+        let worker = format!("cpu{}", rayon::current_thread_index().unwrap_or(0));
+        metrics::HASHED_BYTES.add(&[("worker", &worker)], read_reply.info.len as f64);
 
         tx_nonce_data
             .clone()
@@ -217,6 +221,7 @@ pub fn hash(
                 nonce: offset + read_reply.info.start_nonce,
                 reader_task_processed: read_reply.info.finished,
                 account_id: read_reply.info.account_id,
+                chain: read_reply.info.chain,
             })
             .wait()
             .expect("CPU worker failed to send nonce data");
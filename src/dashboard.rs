@@ -0,0 +1,332 @@
+//! A full-screen view of the rounds, a bar per drive and the latest log lines, shown instead of
+//! the console log if stdout is a terminal.
+
+use crate::miner::RoundStatus;
+use crate::reader::DriveProgress;
+use crate::status::Status;
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use log4rs::append::console::ConsoleAppender;
+use log4rs::append::Append;
+use log4rs::encode::writer::simple::SimpleWriter;
+use log4rs::encode::Encode;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io;
+use std::panic;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tui::backend::{Backend, CrosstermBackend};
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::style::{Color, Style};
+use tui::text::Spans;
+use tui::widgets::{Block, Borders, Gauge, Paragraph};
+use tui::{Frame, Terminal};
+
+const LOG_LINES: usize = 1000;
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+const DRIVE_NAME_WIDTH: u16 = 24;
+const DRIVE_SPEED_WIDTH: u16 = 14;
+
+// the latest log lines, filled by the log pane appender
+static LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+// log lines go to the console unless the dashboard is on screen
+static SHOWN: AtomicBool = AtomicBool::new(false);
+
+/// Takes the place of the console appender while the dashboard is shown, and passes log lines on
+/// to the console before and after that.
+#[derive(Debug)]
+pub struct LogPane {
+    encoder: Box<dyn Encode>,
+    console: ConsoleAppender,
+}
+
+impl LogPane {
+    pub fn new(encoder: Box<dyn Encode>, console: ConsoleAppender) -> LogPane {
+        LogPane { encoder, console }
+    }
+}
+
+impl Append for LogPane {
+    fn append(&self, record: &log::Record) -> Result<(), Box<dyn Error + Sync + Send>> {
+        if !SHOWN.load(Ordering::SeqCst) {
+            return self.console.append(record);
+        }
+        let mut line = SimpleWriter(Vec::new());
+        self.encoder.encode(&mut line, record)?;
+        let line = String::from_utf8_lossy(&line.0).trim_end().to_owned();
+        let mut log = LOG.lock().unwrap();
+        if log.len() == LOG_LINES {
+            log.pop_front();
+        }
+        log.push_back(line);
+        Ok(())
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+    }
+}
+
+/// Shows the dashboard until `q` or Ctrl-C is pressed, which ends the miner.
+pub fn start(status: Arc<Status>) {
+    // a panic would leave the terminal in raw mode otherwise
+    let panic_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        leave();
+        panic_hook(info);
+    }));
+
+    thread::spawn(move || {
+        let res = run(&status);
+        leave();
+        match res {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("dashboard failed: {}", e);
+                process::exit(1);
+            }
+        }
+    });
+}
+
+fn run(status: &Status) -> io::Result<()> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+    terminal.hide_cursor()?;
+    SHOWN.store(true, Ordering::SeqCst);
+    loop {
+        let rounds = status.round();
+        let drives = status.drives();
+        terminal.draw(|f| draw(f, &rounds, &drives))?;
+
+        if event::poll(REFRESH_INTERVAL)? {
+            if let Event::Key(key) = event::read()? {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.code == KeyCode::Char('q') || ctrl_c {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+// restores the terminal and prints what was logged while the dashboard was shown
+fn leave() {
+    let shown = SHOWN.swap(false, Ordering::SeqCst);
+    let _ = restore_terminal();
+    if shown {
+        for line in LOG.lock().unwrap().drain(..) {
+            println!("{}", line);
+        }
+    }
+}
+
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen, crossterm::cursor::Show)
+}
+
+fn draw<B: Backend>(f: &mut Frame<B>, rounds: &[RoundStatus], drives: &[DriveProgress]) {
+    let round_lines = round_lines(rounds);
+    let area = f.size();
+    // the log pane gets what is left, but at least a few lines
+    let drives_height = (drives.len() as u16 + 2).min(area.height / 2);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(round_lines.len() as u16 + 2),
+            Constraint::Length(drives_height),
+            Constraint::Min(3),
+        ])
+        .split(area);
+
+    let block = Block::default().borders(Borders::ALL).title(" Rounds ");
+    f.render_widget(Paragraph::new(round_lines).block(block), chunks[0]);
+    draw_drives(f, chunks[1], rounds, drives);
+    draw_log(f, chunks[2]);
+}
+
+fn round_lines(rounds: &[RoundStatus]) -> Vec<Spans<'static>> {
+    let mut lines = Vec::new();
+    for round in rounds {
+        let state = if round.outage {
+            "outage".to_owned()
+        } else if round.scanning {
+            format!("scanning {:.1}s", round.round_time_ms as f64 / 1000.0)
+        } else {
+            format!("finished in {:.1}s", round.round_time_ms as f64 / 1000.0)
+        };
+        lines.push(Spans::from(format!(
+            "{}: height={}, scoop={}, {}",
+            round.name.as_deref().unwrap_or("main"),
+            round.height,
+            round.scoop,
+            state
+        )));
+        for best in &round.best_deadlines {
+            lines.push(Spans::from(format!(
+                "  {}: best deadline {}",
+                best.account,
+                format_deadline(best.deadline)
+            )));
+        }
+    }
+    lines
+}
+
+fn draw_drives<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    rounds: &[RoundStatus],
+    drives: &[DriveProgress],
+) {
+    let block = Block::default().borders(Borders::ALL).title(" Drives ");
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    for (i, drive) in drives.iter().take(inner.height as usize).enumerate() {
+        let row = Rect::new(inner.x, inner.y + i as u16, inner.width, 1);
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Length(DRIVE_NAME_WIDTH),
+                Constraint::Min(10),
+                Constraint::Length(DRIVE_SPEED_WIDTH),
+            ])
+            .split(row);
+
+        // drives are read for every chain, the first one goes without a name
+        let name = match rounds
+            .get(drive.chain)
+            .and_then(|round| round.name.as_ref())
+        {
+            Some(chain) => format!("{} ({})", drive.drive, chain),
+            None => drive.drive.clone(),
+        };
+        let ratio = if drive.bytes == 0 {
+            1.0
+        } else {
+            (drive.bytes_read as f64 / drive.bytes as f64).min(1.0)
+        };
+        let color = if drive.finished {
+            Color::Green
+        } else {
+            Color::Yellow
+        };
+        f.render_widget(Paragraph::new(name), columns[0]);
+        f.render_widget(
+            Gauge::default()
+                .gauge_style(Style::default().fg(color))
+                .ratio(ratio),
+            columns[1],
+        );
+        f.render_widget(
+            Paragraph::new(format!(" {:.0} MiB/s", drive.speed_mib_s)),
+            columns[2],
+        );
+    }
+}
+
+fn draw_log<B: Backend>(f: &mut Frame<B>, area: Rect) {
+    let block = Block::default().borders(Borders::ALL).title(" Log ");
+    let height = block.inner(area).height as usize;
+    let log = LOG.lock().unwrap();
+    let lines: Vec<Spans> = log
+        .iter()
+        .skip(log.len().saturating_sub(height))
+        .map(|line| Spans::from(line.clone()))
+        .collect();
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+// deadlines in days, hours, minutes and seconds
+fn format_deadline(deadline: u64) -> String {
+    let (days, secs) = (deadline / 86400, deadline % 86400);
+    let time = format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    );
+    if days > 0 {
+        format!("{}d {}", days, time)
+    } else {
+        time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miner::BestDeadline;
+    use tui::backend::TestBackend;
+
+    #[test]
+    fn test_draw() {
+        let rounds = vec![RoundStatus {
+            chain: 0,
+            name: None,
+            upstream: "http://127.0.0.1:8080/".to_owned(),
+            height: 100,
+            scoop: 7,
+            base_target: 1,
+            generation_signature: String::new(),
+            target_deadline: u64::MAX,
+            best_deadlines: vec![BestDeadline {
+                account_id: 1,
+                account: "1".to_owned(),
+                deadline: 3723,
+            }],
+            scanning: true,
+            round_time_ms: 1500,
+            reader_tasks: 1,
+            processed_reader_tasks: 0,
+            capacity_bytes: 0,
+            outage: false,
+        }];
+        let drives = vec![DriveProgress {
+            drive: "sda".to_owned(),
+            chain: 0,
+            height: 100,
+            plots: 2,
+            plots_read: 1,
+            bytes: 2 << 20,
+            bytes_read: 1 << 20,
+            elapsed_ms: 10,
+            speed_mib_s: 100.0,
+            finished: false,
+        }];
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+        terminal.draw(|f| draw(f, &rounds, &drives)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect();
+        assert!(screen.contains("main: height=100, scoop=7, scanning 1.5s"));
+        assert!(screen.contains("1: best deadline 01:02:03"));
+        assert!(screen.contains("sda"));
+        assert!(screen.contains("50%"));
+        assert!(screen.contains("100 MiB/s"));
+    }
+
+    #[test]
+    fn test_format_deadline() {
+        assert_eq!(format_deadline(0), "00:00:00");
+        assert_eq!(format_deadline(3723), "01:02:03");
+        assert_eq!(format_deadline(2 * 86400 + 59), "2d 00:00:59");
+    }
+}
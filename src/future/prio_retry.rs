@@ -4,9 +4,12 @@
 //! Otherwise it will be delayed according to the number of times that it has been enqueued
 //! consecutively.
 //! New items will replace old items and start with a delay of 0.
+//! Items are kept in slots by key, only items with the same key replace each other.
 
 use futures::stream::{Fuse, Stream};
 use futures::{Async, Future, Poll};
+use std::collections::HashMap;
+use std::hash::Hash;
 use std::time::{Duration, Instant};
 use tokio::timer::{self, Delay};
 
@@ -36,25 +39,32 @@ impl<Item> DelayedItem<Item> {
     }
 }
 
-pub struct PrioRetry<S>
+pub struct PrioRetry<S, K, F>
 where
     S: Stream,
     S::Item: Ord + Clone + Eq,
+    K: Hash + Eq,
+    F: Fn(&S::Item) -> K,
 {
     delay_duration: Duration,
-    delayed_item: Option<DelayedItem<S::Item>>,
+    delayed_items: HashMap<K, DelayedItem<S::Item>>,
+    key: F,
     stream: Fuse<S>,
 }
 
-impl<S> PrioRetry<S>
+impl<S, K, F> PrioRetry<S, K, F>
 where
     S: Stream,
     S::Item: Ord + Clone + Eq,
+    K: Hash + Eq,
+    F: Fn(&S::Item) -> K,
 {
-    pub fn new(stream: S, delay_duration: Duration) -> Self {
+    /// Items are put in the slot of the key returned by `key`.
+    pub fn new(stream: S, delay_duration: Duration, key: F) -> Self {
         Self {
             delay_duration,
-            delayed_item: None,
+            delayed_items: HashMap::new(),
+            key,
             stream: stream.fuse(),
         }
     }
@@ -74,10 +84,12 @@ enum Kind<T> {
     Timer(timer::Error),
 }
 
-impl<S> Stream for PrioRetry<S>
+impl<S, K, F> Stream for PrioRetry<S, K, F>
 where
     S: Stream,
     S::Item: Ord + Clone + Eq,
+    K: Hash + Eq,
+    F: Fn(&S::Item) -> K,
 {
     type Item = S::Item;
     type Error = Error<S::Error>;
@@ -89,11 +101,12 @@ where
                     break;
                 }
                 Ok(Async::Ready(Some(new_item))) => {
-                    // check if we currently have a delay item
-                    if let Some(ref mut delayed_item) = self.delayed_item {
+                    // check if we currently have a delay item in the slot
+                    let key = (self.key)(&new_item);
+                    if let Some(delayed_item) = self.delayed_items.get_mut(&key) {
                         if delayed_item.value < new_item {
                             // we have new item, this one will be yielded instantly
-                            self.delayed_item = Some(DelayedItem::new(new_item.clone()));
+                            *delayed_item = DelayedItem::new(new_item.clone());
                             return Ok(Async::Ready(Some(new_item)));
                         } else if delayed_item.value == new_item {
                             // if the current item was requeued, then we will yield it with a backoff
@@ -101,7 +114,8 @@ where
                         }
                     } else {
                         // we have new item, this one will be yielded instantly
-                        self.delayed_item = Some(DelayedItem::new(new_item.clone()));
+                        self.delayed_items
+                            .insert(key, DelayedItem::new(new_item.clone()));
                         return Ok(Async::Ready(Some(new_item)));
                     }
                 }
@@ -114,7 +128,7 @@ where
             }
         }
 
-        if let Some(ref mut delayed_item) = self.delayed_item {
+        for delayed_item in self.delayed_items.values_mut() {
             if let Some(ref mut delay) = delayed_item.delay {
                 match delay.poll() {
                     Ok(Async::NotReady) => {}
@@ -128,7 +142,7 @@ where
                     }
                 }
             }
-        };
+        }
 
         Ok(Async::NotReady)
     }
@@ -149,7 +163,7 @@ mod tests {
             .map(move |_| items.next().unwrap())
             .map_err(|e| error!("can't consume interval: {:?}", e));
         let exp: Vec<i64> = vec![0, 1, 2, 3, 3, 3, 6, 7];
-        let stream = PrioRetry::new(items, Duration::from_millis(100));
+        let stream = PrioRetry::new(items, Duration::from_millis(100), |_| ());
         let res = stream.collect();
         tokio::run(res.then(move |res| {
             match res {
@@ -159,4 +173,21 @@ mod tests {
             Ok(())
         }));
     }
+
+    #[test]
+    fn test_prio_retry_keys() {
+        // (key, value): a better item of one key doesn't replace the item of another key
+        let mut items = vec![(0, 1), (1, 1), (0, 1), (1, 2), (0, 1), (1, 3)].into_iter();
+        let len = items.len();
+        let items = Interval::new(Instant::now(), Duration::from_millis(200))
+            .take(len as u64)
+            .map(move |_| items.next().unwrap())
+            .map_err(|e| error!("can't consume interval: {:?}", e));
+        let exp: Vec<(i64, i64)> = vec![(0, 1), (1, 1), (0, 1), (1, 2), (0, 1), (1, 3)];
+        let stream = PrioRetry::new(items, Duration::from_millis(10), |item| item.0);
+        // panics inside of tokio::run are swallowed, so the items are checked out here
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let items = rt.block_on(stream.collect()).unwrap();
+        assert_eq!(items, exp, "can't get expected items from prio retry");
+    }
 }
@@ -1,3 +1,4 @@
+use crate::metrics;
 use crate::miner::{Buffer, NonceData};
 use crate::ocl::GpuContext;
 use crate::ocl::{gpu_hash, gpu_transfer};
@@ -33,6 +34,7 @@ pub fn create_gpu_worker_task(
                             nonce: 0,
                             reader_task_processed: read_reply.info.finished,
                             account_id: read_reply.info.account_id,
+                            chain: read_reply.info.chain,
                         })
                         .wait()
                         .expect("GPU worker failed to send nonce data");
@@ -43,8 +45,8 @@ pub fn create_gpu_worker_task(
                 continue;
             }
 
-            // consume and ignore all signals
-            if read_reply.info.len == 1 && read_reply.info.gpu_signal > 0 {
+            // consume and ignore all signals, the start signal carries the drive count in len
+            if read_reply.info.gpu_signal > 0 {
                 continue;
             }
 
@@ -60,6 +62,8 @@ pub fn create_gpu_worker_task(
             );
             let deadline = result.0;
             let offset = result.1;
+            let worker = format!("gpu{}", buffer.get_id() - 1);
+            metrics::HASHED_BYTES.add(&[("worker", &worker)], read_reply.info.len as f64);
 
             tx_nonce_data
                 .clone()
@@ -71,6 +75,7 @@ pub fn create_gpu_worker_task(
                     nonce: offset + read_reply.info.start_nonce,
                     reader_task_processed: read_reply.info.finished,
                     account_id: read_reply.info.account_id,
+                    chain: read_reply.info.chain,
                 })
                 .wait()
                 .expect("GPU worker failed to cue empty buffer");
@@ -1,3 +1,4 @@
+use crate::metrics;
 use crate::miner::{Buffer, NonceData};
 use crate::ocl::GpuContext;
 use crate::ocl::{gpu_hash, gpu_transfer, gpu_transfer_and_hash};
@@ -14,7 +15,6 @@ pub fn create_gpu_worker_task_async(
     tx_empty_buffers: Sender<Box<dyn Buffer + Send>>,
     tx_nonce_data: mpsc::Sender<NonceData>,
     context_mu: Arc<GpuContext>,
-    num_drives: usize,
 ) -> impl FnOnce() {
     move || {
         let mut new_round = true;
@@ -28,11 +28,15 @@ pub fn create_gpu_worker_task_async(
             start_nonce: 0,
             finished: false,
             account_id: 0,
+            chain: 0,
             gpu_signal: 0,
         };
         let mut drive_count = 0;
+        let mut num_drives = 0;
         let (tx_sink, rx_sink) = crossbeam_channel::bounded(1);
         let mut active_height = 0;
+        // labels the hashed bytes, known from the first buffer on
+        let mut worker = String::new();
         for read_reply in rx_read_replies {
             let buffer = read_reply.buffer;
             // handle empty buffers (read errors) && benchmark
@@ -50,6 +54,7 @@ pub fn create_gpu_worker_task_async(
                             nonce: 0,
                             reader_task_processed: read_reply.info.finished,
                             account_id: read_reply.info.account_id,
+                            chain: read_reply.info.chain,
                         })
                         .wait()
                         .expect("GPU async worker failed to send nonce data");
@@ -70,6 +75,8 @@ pub fn create_gpu_worker_task_async(
                     }
                 }
                 drive_count = 0;
+                // the start signal carries the number of drives read in this round
+                num_drives = read_reply.info.len;
                 active_height = read_reply.info.height;
                 new_round = true;
                 continue;
@@ -86,6 +93,8 @@ pub fn create_gpu_worker_task_async(
                     );
                     let deadline = result.0;
                     let offset = result.1;
+                    metrics::HASHED_BYTES
+                        .add(&[("worker", &worker)], last_buffer_info_a.len as f64);
 
                     tx_nonce_data
                         .clone()
@@ -97,6 +106,7 @@ pub fn create_gpu_worker_task_async(
                             nonce: offset + last_buffer_info_a.start_nonce,
                             reader_task_processed: last_buffer_info_a.finished,
                             account_id: last_buffer_info_a.account_id,
+                            chain: last_buffer_info_a.chain,
                         })
                         .wait()
                         .expect("GPU async worker failed to send nonce data");
@@ -127,6 +137,7 @@ pub fn create_gpu_worker_task_async(
                 );
                 let deadline = result.0;
                 let offset = result.1;
+                metrics::HASHED_BYTES.add(&[("worker", &worker)], last_buffer_info_a.len as f64);
 
                 tx_nonce_data
                     .clone()
@@ -138,6 +149,7 @@ pub fn create_gpu_worker_task_async(
                         nonce: offset + last_buffer_info_a.start_nonce,
                         reader_task_processed: last_buffer_info_a.finished,
                         account_id: last_buffer_info_a.account_id,
+                        chain: last_buffer_info_a.chain,
                     })
                     .wait()
                     .expect("GPU async worker failed to cue empty buffer");
@@ -147,6 +159,7 @@ pub fn create_gpu_worker_task_async(
                         .expect("GPU async worker failed to cue empty buffer from sink")
                 }
             }
+            worker = format!("gpu{}", buffer.get_id() - 1);
             last_buffer_a = buffer.get_gpu_data();
             last_buffer_info_a = read_reply.info;
             new_round = false;
@@ -0,0 +1,404 @@
+//! Rounds and submissions appended to files of JSON lines, so that they survive restarts, and the
+//! report of the `history` subcommand.
+
+use crate::account::Account;
+use crate::requests::{SubmissionOutcome, SubmissionRecord};
+use chrono::{Local, NaiveDate, TimeZone};
+use crossbeam_channel::Sender;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const ROUNDS_FILE: &str = "rounds.jsonl";
+const SUBMISSIONS_FILE: &str = "submissions.jsonl";
+
+// lines for the writer thread, nothing is recorded until set
+static TX: RwLock<Option<Sender<(&'static str, String)>>> = RwLock::new(None);
+
+/// A round that either finished or was interrupted by a new block.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RoundRecord {
+    pub chain: usize,
+    pub name: Option<String>,
+    pub height: u64,
+    pub generation_signature: String,
+    pub scoop: u32,
+    pub base_target: u64,
+    // unix time in seconds
+    pub started: u64,
+    pub finished: Option<u64>,
+    pub interrupted: Option<u64>,
+    pub round_time_ms: i64,
+    // scoop bytes read
+    pub bytes_scanned: u64,
+}
+
+/// Sets the directory records are appended to from now on. Records are written by a thread of
+/// their own, so that recording doesn't block the runtime.
+pub fn init(dir: Option<PathBuf>) {
+    let tx = dir.map(|dir| {
+        let (tx, rx) = crossbeam_channel::unbounded::<(&'static str, String)>();
+        thread::spawn(move || {
+            let mut writer = Writer::new(dir);
+            for (file, line) in rx {
+                if let Err(e) = writer.append(file, &line) {
+                    warn!(
+                        "history: can't write to {}: {}",
+                        writer.dir.join(file).display(),
+                        e
+                    );
+                }
+            }
+        });
+        tx
+    });
+    *TX.write().unwrap() = tx;
+}
+
+/// Unix time in seconds.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn record_round(round: &RoundRecord) {
+    append(ROUNDS_FILE, round);
+}
+
+pub fn record_submission(submission: &SubmissionRecord) {
+    append(SUBMISSIONS_FILE, submission);
+}
+
+fn append<T: Serialize>(file: &'static str, record: &T) {
+    if let Some(tx) = &*TX.read().unwrap() {
+        match to_line(record) {
+            Ok(line) => {
+                let _ = tx.send((file, line));
+            }
+            Err(e) => warn!("history: can't serialize record for {}: {}", file, e),
+        }
+    }
+}
+
+fn to_line<T: Serialize>(record: &T) -> Result<String, serde_json::Error> {
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    Ok(line)
+}
+
+// appends lines to the files of a directory, files are kept open once they have been written to
+struct Writer {
+    dir: PathBuf,
+    files: HashMap<&'static str, File>,
+}
+
+impl Writer {
+    fn new(dir: PathBuf) -> Writer {
+        Writer {
+            dir,
+            files: HashMap::new(),
+        }
+    }
+
+    fn append(&mut self, file: &'static str, line: &str) -> io::Result<()> {
+        if !self.files.contains_key(file) {
+            fs::create_dir_all(&self.dir)?;
+            let fh = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.dir.join(file))?;
+            self.files.insert(file, fh);
+        }
+        let result = self.files.get_mut(file).unwrap().write_all(line.as_bytes());
+        // reopened for the next line, the file might have been moved away
+        if result.is_err() {
+            self.files.remove(file);
+        }
+        result
+    }
+}
+
+// a line cut short by a crash is skipped along with anything else that doesn't parse
+fn load<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let records = fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                warn!("history: skipping {}:{}: {}", path.display(), i + 1, e);
+                None
+            }
+        })
+        .collect();
+    Ok(records)
+}
+
+/// Parses a date as `YYYY-MM-DD` in local time to the unix time of the start of that day.
+pub fn parse_date(date: &str) -> Result<u64, Box<dyn Error>> {
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|e| format!("invalid date {}, expected YYYY-MM-DD: {}", date, e))?;
+    let start = Local
+        .from_local_datetime(&date.and_hms(0, 0, 0))
+        .earliest()
+        .ok_or_else(|| format!("{} doesn't exist in the local time zone", date))?;
+    Ok(start.timestamp().max(0) as u64)
+}
+
+#[derive(Default)]
+struct RoundStats {
+    rounds: usize,
+    finished: usize,
+    interrupted: usize,
+    // of finished rounds
+    round_time_ms: i64,
+    max_round_time_ms: i64,
+    bytes_scanned: u64,
+}
+
+#[derive(Default)]
+struct AccountStats {
+    submissions: usize,
+    accepted: usize,
+    rejected: usize,
+    // busy pools and failed requests, both are retried
+    retries: usize,
+    // deadline and height
+    best_deadline: Option<(u64, u64)>,
+}
+
+impl AccountStats {
+    // of the submissions the upstream gave a final answer to
+    fn acceptance_rate(&self) -> f64 {
+        if self.accepted + self.rejected == 0 {
+            0.0
+        } else {
+            self.accepted as f64 * 100.0 / (self.accepted + self.rejected) as f64
+        }
+    }
+}
+
+struct Report {
+    chains: BTreeMap<Option<String>, RoundStats>,
+    accounts: BTreeMap<u64, AccountStats>,
+}
+
+/// Rounds started and submissions made in `[from, to)`.
+fn report(rounds: &[RoundRecord], submissions: &[SubmissionRecord], from: u64, to: u64) -> Report {
+    let mut chains: BTreeMap<Option<String>, RoundStats> = BTreeMap::new();
+    for round in rounds
+        .iter()
+        .filter(|r| r.started >= from && r.started < to)
+    {
+        let stats = chains.entry(round.name.clone()).or_default();
+        stats.rounds += 1;
+        stats.bytes_scanned += round.bytes_scanned;
+        if round.finished.is_some() {
+            stats.finished += 1;
+            stats.round_time_ms += round.round_time_ms;
+            stats.max_round_time_ms = stats.max_round_time_ms.max(round.round_time_ms);
+        } else {
+            stats.interrupted += 1;
+        }
+    }
+
+    let mut accounts: BTreeMap<u64, AccountStats> = BTreeMap::new();
+    for submission in submissions.iter().filter(|s| s.time >= from && s.time < to) {
+        let stats = accounts.entry(submission.account_id).or_default();
+        stats.submissions += 1;
+        match submission.outcome {
+            SubmissionOutcome::Accepted => {
+                stats.accepted += 1;
+                let best_deadline = stats.best_deadline.map_or(u64::MAX, |(d, _)| d);
+                if submission.deadline < best_deadline {
+                    stats.best_deadline = Some((submission.deadline, submission.height));
+                }
+            }
+            SubmissionOutcome::DeadlineMismatch | SubmissionOutcome::NotAccepted => {
+                stats.rejected += 1
+            }
+            SubmissionOutcome::PoolBusy | SubmissionOutcome::Failed => stats.retries += 1,
+        }
+    }
+    Report { chains, accounts }
+}
+
+/// Logs best deadlines, acceptance rates and round times of the history in `dir` between `from`
+/// and `to`, unix times in seconds.
+pub fn run(dir: &Path, from: u64, to: u64) -> Result<(), Box<dyn Error>> {
+    let rounds: Vec<RoundRecord> = load(&dir.join(ROUNDS_FILE))?;
+    let submissions: Vec<SubmissionRecord> = load(&dir.join(SUBMISSIONS_FILE))?;
+    let report = report(&rounds, &submissions, from, to);
+
+    let format_time = |time: u64| Local.timestamp(time as i64, 0).format("%Y-%m-%d %H:%M:%S");
+    info!(
+        "history: from={}, to={}, rounds={}, submissions={}",
+        format_time(from),
+        format_time(to.min(now())),
+        report.chains.values().map(|s| s.rounds).sum::<usize>(),
+        report
+            .accounts
+            .values()
+            .map(|s| s.submissions)
+            .sum::<usize>()
+    );
+    for (name, stats) in &report.chains {
+        let chain = match name {
+            Some(name) => format!("chain={}, ", name),
+            None => String::new(),
+        };
+        let avg_round_time_ms = if stats.finished == 0 {
+            0
+        } else {
+            stats.round_time_ms / stats.finished as i64
+        };
+        info!(
+            "history: {}rounds={}, finished={}, interrupted={}, roundtime_avg={}ms, \
+             roundtime_max={}ms, scanned={:.2}GiB",
+            chain,
+            stats.rounds,
+            stats.finished,
+            stats.interrupted,
+            avg_round_time_ms,
+            stats.max_round_time_ms,
+            stats.bytes_scanned as f64 / 1024.0 / 1024.0 / 1024.0
+        );
+    }
+    for (&account_id, stats) in &report.accounts {
+        let best_deadline = match stats.best_deadline {
+            Some((deadline, height)) => format!("{} (height={})", deadline, height),
+            None => "none".to_owned(),
+        };
+        info!(
+            "history: account={}, submissions={}, accepted={}, rejected={}, retries={}, \
+             acceptance={:.1}%, best_deadline={}",
+            Account(account_id),
+            stats.submissions,
+            stats.accepted,
+            stats.rejected,
+            stats.retries,
+            stats.acceptance_rate(),
+            best_deadline
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn round(name: Option<&str>, started: u64, finished: bool) -> RoundRecord {
+        RoundRecord {
+            chain: name.map_or(0, |_| 1),
+            name: name.map(str::to_owned),
+            height: started,
+            generation_signature: String::new(),
+            scoop: 0,
+            base_target: 1,
+            started,
+            finished: if finished { Some(started + 10) } else { None },
+            interrupted: if finished { None } else { Some(started + 5) },
+            round_time_ms: if finished { 10_000 } else { 5_000 },
+            bytes_scanned: 1 << 30,
+        }
+    }
+
+    fn submission(time: u64, deadline: u64, outcome: SubmissionOutcome) -> SubmissionRecord {
+        SubmissionRecord {
+            time,
+            height: time,
+            account_id: 1,
+            account: "1".to_owned(),
+            nonce: 7,
+            deadline,
+            upstream: "http://127.0.0.1:8080/".to_owned(),
+            outcome,
+            deadline_pool: Some(deadline),
+            message: None,
+            latency_ms: 10,
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let dir = env::temp_dir().join(format!("scavenger_history_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut writer = Writer::new(dir.clone());
+        for record in &[
+            round(None, 100, true),
+            round(None, 200, false),
+            round(Some("bhd"), 300, true),
+            round(None, 1000, true),
+        ] {
+            writer
+                .append(ROUNDS_FILE, &to_line(record).unwrap())
+                .unwrap();
+        }
+        let outcomes = [
+            (100, 50, SubmissionOutcome::Accepted),
+            (110, 40, SubmissionOutcome::PoolBusy),
+            (120, 40, SubmissionOutcome::Accepted),
+            (200, 30, SubmissionOutcome::NotAccepted),
+            (1000, 10, SubmissionOutcome::Accepted),
+        ];
+        for &(time, deadline, outcome) in &outcomes {
+            let line = to_line(&submission(time, deadline, outcome)).unwrap();
+            writer.append(SUBMISSIONS_FILE, &line).unwrap();
+        }
+        // cut short by a crash
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.join(SUBMISSIONS_FILE))
+            .unwrap();
+        file.write_all(b"{\"time\":").unwrap();
+
+        let rounds: Vec<RoundRecord> = load(&dir.join(ROUNDS_FILE)).unwrap();
+        let submissions: Vec<SubmissionRecord> = load(&dir.join(SUBMISSIONS_FILE)).unwrap();
+        assert_eq!(rounds.len(), 4);
+        assert_eq!(submissions.len(), 5);
+
+        let report = report(&rounds, &submissions, 0, 1000);
+        let main = &report.chains[&None];
+        assert_eq!((main.rounds, main.finished, main.interrupted), (2, 1, 1));
+        assert_eq!(
+            (main.round_time_ms, main.max_round_time_ms),
+            (10_000, 10_000)
+        );
+        assert_eq!(main.bytes_scanned, 2 << 30);
+        assert_eq!(report.chains[&Some("bhd".to_owned())].rounds, 1);
+        let account = &report.accounts[&1];
+        assert_eq!(account.submissions, 4);
+        assert_eq!(
+            (account.accepted, account.rejected, account.retries),
+            (2, 1, 1)
+        );
+        assert_eq!(account.best_deadline, Some((40, 120)));
+        assert!((account.acceptance_rate() - 200.0 / 3.0).abs() < 1e-9);
+
+        assert!(run(&dir, 0, u64::MAX).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let day = parse_date("2019-01-02").unwrap();
+        assert_eq!(parse_date("2019-01-03").unwrap() - day, 86400);
+        assert!(parse_date("2019-13-01").is_err());
+        assert!(parse_date("yesterday").is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! Secret phrases encrypted with a password, so that they don't have to be in the config.
+
+use crate::config::{Cfg, SecretPhrase};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::num::NonZeroU32;
+use std::path::Path;
+
+// lets services start without a prompt
+const PASSWORD_ENV: &str = "SCAVENGER_KEYSTORE_PASSWORD";
+const PBKDF2_ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+
+#[derive(Serialize, Deserialize)]
+struct Keystore {
+    iterations: u32,
+    salt: String,
+    secret_phrases: HashMap<u64, Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Keystore {
+    fn new() -> Result<Keystore, Box<dyn Error>> {
+        let mut salt = [0u8; SALT_LEN];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .map_err(|_| "can't generate salt")?;
+        Ok(Keystore {
+            iterations: PBKDF2_ITERATIONS,
+            salt: hex::encode(salt),
+            secret_phrases: HashMap::new(),
+        })
+    }
+
+    fn load(path: &Path) -> Result<Keystore, Box<dyn Error>> {
+        let keystore = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&keystore)?)
+    }
+
+    // replaces the keystore atomically, a failed write mustn't lose the secret phrases
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let tmp_path = path.with_extension("tmp");
+        // the mode only applies to new files
+        let _ = fs::remove_file(&tmp_path);
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp_path)?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    fn key(&self, password: &str) -> Result<LessSafeKey, Box<dyn Error>> {
+        let iterations = NonZeroU32::new(self.iterations).ok_or("invalid iteration count")?;
+        let salt = hex::decode(&self.salt)?;
+        let mut key = [0u8; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            password.as_bytes(),
+            &mut key,
+        );
+        let key = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| "invalid key")?;
+        Ok(LessSafeKey::new(key))
+    }
+
+    fn decrypt(&self, password: &str) -> Result<HashMap<u64, SecretPhrase>, Box<dyn Error>> {
+        let key = self.key(password)?;
+        let mut secret_phrases = HashMap::new();
+        for (account_id, entry) in &self.secret_phrases {
+            let nonce = Nonce::try_assume_unique_for_key(&hex::decode(&entry.nonce)?)
+                .map_err(|_| "invalid nonce")?;
+            let mut data = hex::decode(&entry.ciphertext)?;
+            // the account id is authenticated, entries can't be swapped between accounts
+            let secret_phrase = key
+                .open_in_place(nonce, Aad::from(account_id.to_be_bytes()), &mut data)
+                .map_err(|_| "wrong password or corrupted keystore")?;
+            secret_phrases.insert(
+                *account_id,
+                SecretPhrase::new(String::from_utf8(secret_phrase.to_vec())?),
+            );
+        }
+        Ok(secret_phrases)
+    }
+
+    fn insert(
+        &mut self,
+        password: &str,
+        account_id: u64,
+        secret_phrase: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let key = self.key(password)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| "can't generate nonce")?;
+        let mut data = secret_phrase.as_bytes().to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(account_id.to_be_bytes()),
+            &mut data,
+        )
+        .map_err(|_| "encryption failed")?;
+        self.secret_phrases.insert(
+            account_id,
+            Entry {
+                nonce: hex::encode(nonce),
+                ciphertext: hex::encode(data),
+            },
+        );
+        Ok(())
+    }
+}
+
+fn password(prompt: &str) -> Result<String, Box<dyn Error>> {
+    match env::var(PASSWORD_ENV) {
+        Ok(password) => Ok(password),
+        Err(_) => Ok(rpassword::read_password_from_tty(Some(prompt))?),
+    }
+}
+
+/// Unlocks the keystore of the config and adds its secret phrases, entries of the config win.
+pub fn unlock(cfg: &mut Cfg) -> Result<(), Box<dyn Error>> {
+    let path = match &cfg.keystore_file {
+        Some(path) => path.clone(),
+        None => return Ok(()),
+    };
+    let keystore = Keystore::load(&path)?;
+    let password = password(&format!("password of {}: ", path.display()))?;
+    for (account_id, secret_phrase) in keystore.decrypt(&password)? {
+        if cfg.account_id_to_secret_phrase.contains_key(&account_id) {
+            warn!(
+                "keystore: account {} is in the config as well, using the config",
+                account_id
+            );
+            continue;
+        }
+        cfg.account_id_to_secret_phrase
+            .insert(account_id, secret_phrase);
+    }
+    info!(
+        "keystore: unlocked, file={}, accounts={}",
+        path.display(),
+        keystore.secret_phrases.len()
+    );
+    Ok(())
+}
+
+/// Asks for the secret phrase of `account_id` and stores it in the keystore at `path`, which is
+/// created if it doesn't exist yet.
+pub fn add(path: &Path, account_id: u64) -> Result<(), Box<dyn Error>> {
+    let mut keystore = if path.exists() {
+        Keystore::load(path)?
+    } else {
+        Keystore::new()?
+    };
+    let secret_phrase = rpassword::read_password_from_tty(Some(&format!(
+        "secret phrase of account {}: ",
+        account_id
+    )))?;
+    let password = password(&format!("password of {}: ", path.display()))?;
+    if keystore.secret_phrases.is_empty() && env::var(PASSWORD_ENV).is_err() {
+        let repeated = rpassword::read_password_from_tty(Some("repeat password: "))?;
+        if password != repeated {
+            return Err(From::from("passwords don't match"));
+        }
+    }
+    // all entries share the password
+    keystore.decrypt(&password)?;
+    keystore.insert(&password, account_id, &secret_phrase)?;
+    keystore.save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keystore() {
+        let path = env::temp_dir().join(format!("scavenger_keystore_{}.json", std::process::id()));
+        let mut keystore = Keystore::new().unwrap();
+        keystore.insert("password", 1, "first secret").unwrap();
+        keystore.insert("password", 2, "second secret").unwrap();
+        keystore.save(&path).unwrap();
+        assert!(!path.with_extension("tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let keystore = Keystore::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let secret_phrases = keystore.decrypt("password").unwrap();
+        assert_eq!(secret_phrases[&1].expose(), "first secret");
+        assert_eq!(secret_phrases[&2].expose(), "second secret");
+        assert!(keystore.decrypt("wrong").is_err());
+
+        // ciphertexts are bound to their account
+        let mut keystore = keystore;
+        let entry = keystore.secret_phrases.remove(&1).unwrap();
+        keystore.secret_phrases.insert(3, entry);
+        assert!(keystore.decrypt("password").is_err());
+    }
+}
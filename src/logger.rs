@@ -1,14 +1,19 @@
 use crate::config::Cfg;
+use crate::dashboard::LogPane;
 
-use log::LevelFilter;
+use log::kv::{self, Key, Value, VisitSource};
+use log::{LevelFilter, Record};
 use log4rs::append::console::ConsoleAppender;
 use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
 use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
 use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
 use log4rs::append::rolling_file::RollingFileAppender;
+use log4rs::append::Append;
 use log4rs::config::{Appender, Config, Root};
 use log4rs::encode::pattern::PatternEncoder;
+use log4rs::encode::{self, Encode};
 use log4rs::filter::threshold::ThresholdFilter;
+use std::error::Error;
 
 fn to_log_level(s: &str, default: log::LevelFilter) -> log::LevelFilter {
     match s.to_lowercase().as_str() {
@@ -22,6 +27,70 @@ fn to_log_level(s: &str, default: log::LevelFilter) -> log::LevelFilter {
     }
 }
 
+/// Writes every event as a line of JSON. The key-values logged along with an event, such as the
+/// height of a new block or the deadline of a submission, become fields of their own.
+#[derive(Debug)]
+struct JsonEncoder;
+
+impl Encode for JsonEncoder {
+    fn encode(
+        &self,
+        w: &mut dyn encode::Write,
+        record: &Record,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let message = record.args().to_string();
+        let mut line = format!(
+            "{{\"time\":{},\"level\":{},\"target\":{},\"message\":{}",
+            json_string(&chrono::Local::now().to_rfc3339()),
+            json_string(record.level().as_str()),
+            json_string(record.target()),
+            // messages are padded to overwrite the progress bar
+            json_string(message.trim()),
+        );
+        record
+            .key_values()
+            .visit(&mut JsonFields(&mut line))
+            .map_err(|e| e.to_string())?;
+        line.push_str("}\n");
+        w.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+struct JsonFields<'a>(&'a mut String);
+
+impl<'kvs, 'a> VisitSource<'kvs> for JsonFields<'a> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(v) = value.to_bool() {
+            serde_json::Value::from(v)
+        } else if let Some(v) = value.to_u64() {
+            serde_json::Value::from(v)
+        } else if let Some(v) = value.to_i64() {
+            serde_json::Value::from(v)
+        } else if let Some(v) = value.to_f64() {
+            serde_json::Value::from(v)
+        } else {
+            serde_json::Value::from(value.to_string())
+        };
+        self.0.push(',');
+        self.0.push_str(&json_string(key.as_str()));
+        self.0.push(':');
+        self.0.push_str(&value.to_string());
+        Ok(())
+    }
+}
+
+fn json_string(s: &str) -> String {
+    serde_json::Value::from(s).to_string()
+}
+
+fn encoder(format: &str, pattern: &str) -> Box<dyn Encode> {
+    match format.to_lowercase().as_str() {
+        "json" => Box::new(JsonEncoder),
+        _ => Box::new(PatternEncoder::new(pattern)),
+    }
+}
+
 pub fn init_logger(cfg: &Cfg) -> log4rs::Handle {
     let level_console = to_log_level(&cfg.console_log_level, log::LevelFilter::Info);
     let level_logfile = to_log_level(&cfg.logfile_log_level, log::LevelFilter::Warn);
@@ -38,9 +107,21 @@ pub fn init_logger(cfg: &Cfg) -> log4rs::Handle {
     };
     logfile_log_pattern.push_str(&cfg.logfile_log_pattern);
 
-    let stdout = ConsoleAppender::builder()
-        .encoder(Box::new(PatternEncoder::new(&console_log_pattern)))
-        .build();
+    // the dashboard shows the console log in a pane of its own once it is on screen
+    let stdout: Box<dyn Append> = if cfg.show_dashboard {
+        Box::new(LogPane::new(
+            Box::new(PatternEncoder::new(&console_log_pattern)),
+            ConsoleAppender::builder()
+                .encoder(Box::new(PatternEncoder::new(&console_log_pattern)))
+                .build(),
+        ))
+    } else {
+        Box::new(
+            ConsoleAppender::builder()
+                .encoder(encoder(&cfg.console_log_format, &console_log_pattern))
+                .build(),
+        )
+    };
 
     let roller = FixedWindowRoller::builder()
         .base(1)
@@ -54,20 +135,20 @@ pub fn init_logger(cfg: &Cfg) -> log4rs::Handle {
             .appender(
                 Appender::builder()
                     .filter(Box::new(ThresholdFilter::new(level_console)))
-                    .build("stdout", Box::new(stdout)),
+                    .build("stdout", stdout),
             )
             .build(Root::builder().appender("stdout").build(LevelFilter::Info))
             .unwrap()
     } else {
         let logfile = RollingFileAppender::builder()
-            .encoder(Box::new(PatternEncoder::new(&logfile_log_pattern)))
+            .encoder(encoder(&cfg.logfile_log_format, &logfile_log_pattern))
             .build("log/scavenger.1.log", policy)
             .unwrap();
         Config::builder()
             .appender(
                 Appender::builder()
                     .filter(Box::new(ThresholdFilter::new(level_console)))
-                    .build("stdout", Box::new(stdout)),
+                    .build("stdout", stdout),
             )
             .appender(
                 Appender::builder()
@@ -121,6 +202,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_json_encoder() {
+        let encode = |record: &Record| {
+            let mut line = encode::writer::simple::SimpleWriter(Vec::new());
+            JsonEncoder.encode(&mut line, record).unwrap();
+            let line = String::from_utf8(line.0).unwrap();
+            assert!(line.ends_with('\n'));
+            serde_json::from_str::<serde_json::Value>(&line).unwrap()
+        };
+
+        let kvs: &[(&str, Value)] = &[
+            ("height", Value::from(100u64)),
+            ("drive", Value::from("/mnt/a \"b\"")),
+            ("speed", Value::from(1.5)),
+            ("change", Value::from(-2i64)),
+            ("accepted", Value::from(true)),
+        ];
+        let event = encode(
+            &Record::builder()
+                .args(format_args!("{: <20}", "new block"))
+                .level(log::Level::Info)
+                .target("scavenger::miner")
+                .key_values(&kvs)
+                .build(),
+        );
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["target"], "scavenger::miner");
+        assert_eq!(event["message"], "new block");
+        assert_eq!(event["height"], 100);
+        assert_eq!(event["drive"], "/mnt/a \"b\"");
+        assert_eq!(event["speed"], 1.5);
+        assert_eq!(event["change"], -2);
+        assert_eq!(event["accepted"], true);
+        assert!(event["time"].is_string());
+
+        let event = encode(&Record::builder().args(format_args!("plain")).build());
+        assert_eq!(event.as_object().unwrap().len(), 4);
+    }
+
     #[test]
     fn test_init_logger() {
         use crate::config::load_cfg;
@@ -8,18 +8,31 @@ extern crate clap;
 #[macro_use]
 extern crate log;
 
+mod account;
 mod com;
 mod config;
+mod converter;
 mod cpu_worker;
+mod dashboard;
 mod future;
+mod history;
+mod keystore;
 mod logger;
+mod metrics;
 mod miner;
 mod plot;
+mod plot_index;
+mod plotter;
 mod poc_hashing;
+mod proxy;
 mod reader;
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+mod reader_uring;
 mod requests;
 mod shabal256;
+mod status;
 mod utils;
+mod verifier;
 
 #[cfg(feature = "opencl")]
 mod gpu_worker;
@@ -28,10 +41,16 @@ mod gpu_worker_async;
 #[cfg(feature = "opencl")]
 mod ocl;
 
-use crate::config::load_cfg;
+use crate::config::{load_cfg, Cfg};
+use crate::converter::Converter;
 use crate::miner::Miner;
-use clap::{App, Arg};
+use crate::plotter::Plotter;
+use crate::verifier::Verifier;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use crossterm::tty::IsTty;
 use futures::Future;
+use std::io;
+use std::path::Path;
 use std::process;
 use tokio::runtime::Builder;
 
@@ -109,6 +128,166 @@ fn main() {
                 .help("Location of the config file")
                 .takes_value(true)
                 .default_value("config.yaml"),
+        )
+        .subcommand(
+            SubCommand::with_name("plot")
+                .about("Creates a PoC2 plot file")
+                .arg(
+                    Arg::with_name("id")
+                        .short("i")
+                        .long("id")
+                        .value_name("ID")
+                        .help("Numeric account id or BURST address")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("start nonce")
+                        .short("s")
+                        .long("sn")
+                        .value_name("NONCE")
+                        .help("Start nonce")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("nonces")
+                        .short("n")
+                        .long("n")
+                        .value_name("NONCES")
+                        .help("Number of nonces to plot")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .short("p")
+                        .long("path")
+                        .value_name("DIR")
+                        .help("Target directory")
+                        .takes_value(true)
+                        .default_value("."),
+                )
+                .arg(
+                    Arg::with_name("nonces per cache")
+                        .short("m")
+                        .long("nonces-per-cache")
+                        .value_name("NONCES")
+                        .help("Nonces hashed per write, two caches are allocated")
+                        .takes_value(true)
+                        .default_value("1024"),
+                )
+                .arg(
+                    Arg::with_name("cpu threads")
+                        .short("t")
+                        .long("cpu")
+                        .value_name("THREADS")
+                        .help("Number of hashing threads (default: cpu_threads from config)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("disable direct io")
+                        .short("d")
+                        .long("ddio")
+                        .help("Disables direct i/o")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about(
+                    "Verifies plot files by regenerating sampled nonces \
+                     (exit codes: 0=ok, 1=corrupted, 2=error)",
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .short("p")
+                        .long("path")
+                        .value_name("PATH")
+                        .help("Plot file or directory")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("rate")
+                        .short("r")
+                        .long("rate")
+                        .value_name("RATE")
+                        .help("Fraction of nonces to check, 1 checks every nonce")
+                        .takes_value(true)
+                        .default_value("0.001"),
+                )
+                .arg(
+                    Arg::with_name("cpu threads")
+                        .short("t")
+                        .long("cpu")
+                        .value_name("THREADS")
+                        .help("Number of hashing threads (default: cpu_threads from config)")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("convert")
+                .about(
+                    "Converts a PoC1 plot file to PoC2, an interrupted conversion is resumed \
+                     when run again",
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .short("p")
+                        .long("path")
+                        .value_name("FILE")
+                        .help("PoC1 plot file")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .short("o")
+                        .long("out")
+                        .value_name("DIR")
+                        .help("Writes a converted copy into DIR instead of converting in place")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about(
+                    "Reports best deadlines, acceptance rates and round times of past rounds \
+                     and submissions",
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .short("f")
+                        .long("from")
+                        .value_name("DATE")
+                        .help("First day of the report as YYYY-MM-DD (default: all)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .short("t")
+                        .long("to")
+                        .value_name("DATE")
+                        .help("Last day of the report as YYYY-MM-DD (default: today)")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("keystore")
+                .about(
+                    "Adds a secret phrase to the keystore_file of the config, the keystore is \
+                     created if it doesn't exist",
+                )
+                .arg(
+                    Arg::with_name("id")
+                        .short("i")
+                        .long("id")
+                        .value_name("ID")
+                        .help("Numeric account id or BURST address")
+                        .takes_value(true)
+                        .required(true),
+                ),
         );
     #[cfg(feature = "opencl")]
     let arg = arg.arg(
@@ -122,8 +301,20 @@ fn main() {
     let matches = &arg.get_matches();
     let config = matches.value_of("config").unwrap();
 
-    let cfg_loaded = load_cfg(config);
+    let mut cfg_loaded = load_cfg(config);
+    // the dashboard only takes over the terminal when mining
+    let mining = matches.subcommand_name().is_none() && !matches.is_present("opencl");
+    let no_terminal = cfg_loaded.show_dashboard && !io::stdout().is_tty();
+    cfg_loaded.show_dashboard &= mining && !no_terminal;
+    // progress bars would end up between the json lines
+    if cfg_loaded.show_dashboard || cfg_loaded.console_log_format.eq_ignore_ascii_case("json") {
+        cfg_loaded.show_progress = false;
+    }
     logger::init_logger(&cfg_loaded);
+    account::init_display(&cfg_loaded);
+    if mining && no_terminal {
+        info!("dashboard: stdout isn't a terminal, logging to the console instead");
+    }
 
     info!("Scavenger v.{}", crate_version!());
     #[cfg(feature = "opencl")]
@@ -137,11 +328,161 @@ fn main() {
 
     #[cfg(any(feature = "simd", feature = "neon"))]
     init_cpu_extensions();
+
+    if let Some(matches) = matches.subcommand_matches("plot") {
+        process::exit(plot(matches, &cfg_loaded));
+    }
+    if let Some(matches) = matches.subcommand_matches("verify") {
+        process::exit(verify(matches, &cfg_loaded));
+    }
+    if let Some(matches) = matches.subcommand_matches("convert") {
+        process::exit(convert(matches, &cfg_loaded));
+    }
+    if let Some(matches) = matches.subcommand_matches("keystore") {
+        process::exit(add_to_keystore(matches, &cfg_loaded));
+    }
+    if let Some(matches) = matches.subcommand_matches("history") {
+        process::exit(report_history(matches, &cfg_loaded));
+    }
+
+    if let Err(e) = keystore::unlock(&mut cfg_loaded) {
+        error!("keystore: can't unlock: {}", e);
+        process::exit(1);
+    }
+    if let Err(e) = account::check_secret_phrases(&cfg_loaded) {
+        error!("refusing to start, {}", e);
+        process::exit(1);
+    }
+
     #[cfg(feature = "opencl")]
     ocl::gpu_info(&cfg_loaded);
 
+    history::init(cfg_loaded.history_dir.clone());
     let rt = Builder::new().core_threads(1).build().unwrap();
     let m = Miner::new(cfg_loaded, rt.executor());
     m.run();
     rt.shutdown_on_idle().wait().unwrap();
 }
+
+fn plot(matches: &ArgMatches, cfg: &Cfg) -> i32 {
+    let account_id = match account::parse_account_id(matches.value_of("id").unwrap()) {
+        Ok(account_id) => account_id,
+        Err(e) => {
+            error!("plotting failed: {}", e);
+            return 1;
+        }
+    };
+    let start_nonce = value_t_or_exit!(matches, "start nonce", u64);
+    let nonces = value_t_or_exit!(matches, "nonces", u64);
+    let nonces_per_cache = value_t_or_exit!(matches, "nonces per cache", u64);
+    let cpu_threads = cpu_threads(matches, cfg);
+
+    let plotter = Plotter::new(
+        account_id,
+        start_nonce,
+        nonces,
+        Path::new(matches.value_of("path").unwrap()),
+        nonces_per_cache,
+        cpu_threads,
+        cfg.cpu_thread_pinning,
+        !matches.is_present("disable direct io"),
+        cfg.show_progress,
+    );
+    match plotter.and_then(|plotter| plotter.run()) {
+        Ok(_) => 0,
+        Err(e) => {
+            error!("plotting failed: {}", e);
+            1
+        }
+    }
+}
+
+fn verify(matches: &ArgMatches, cfg: &Cfg) -> i32 {
+    let rate = value_t_or_exit!(matches, "rate", f64);
+    if rate <= 0.0 || rate > 1.0 {
+        error!("verify: rate has to be in (0, 1]");
+        return verifier::EXIT_ERROR;
+    }
+
+    let verifier = Verifier::new(rate, cpu_threads(matches, cfg), cfg.cpu_thread_pinning);
+    verifier.run(Path::new(matches.value_of("path").unwrap()))
+}
+
+fn convert(matches: &ArgMatches, cfg: &Cfg) -> i32 {
+    let converter = Converter::new(
+        Path::new(matches.value_of("path").unwrap()),
+        matches.value_of("out").map(Path::new),
+        cfg.show_progress,
+    );
+    match converter.and_then(|converter| converter.run()) {
+        Ok(_) => 0,
+        Err(e) => {
+            error!("conversion failed: {}", e);
+            1
+        }
+    }
+}
+
+fn add_to_keystore(matches: &ArgMatches, cfg: &Cfg) -> i32 {
+    let account_id = match account::parse_account_id(matches.value_of("id").unwrap()) {
+        Ok(account_id) => account_id,
+        Err(e) => {
+            error!("keystore: {}", e);
+            return 1;
+        }
+    };
+    let path = match &cfg.keystore_file {
+        Some(path) => path,
+        None => {
+            error!("keystore: set keystore_file in the config first");
+            return 1;
+        }
+    };
+    match keystore::add(path, account_id) {
+        Ok(_) => {
+            info!(
+                "keystore: added account {} to {}",
+                account_id,
+                path.display()
+            );
+            0
+        }
+        Err(e) => {
+            error!("keystore: can't add account {}: {}", account_id, e);
+            1
+        }
+    }
+}
+
+fn report_history(matches: &ArgMatches, cfg: &Cfg) -> i32 {
+    let dir = match &cfg.history_dir {
+        Some(dir) => dir,
+        None => {
+            error!("history: set history_dir in the config first");
+            return 1;
+        }
+    };
+    let range = |name: &str, default: u64| match matches.value_of(name) {
+        Some(date) => history::parse_date(date),
+        None => Ok(default),
+    };
+    // the last day is part of the report
+    let res = range("from", 0).and_then(|from| {
+        let to = range("to", history::now())?.saturating_add(86400);
+        history::run(dir, from, to)
+    });
+    match res {
+        Ok(_) => 0,
+        Err(e) => {
+            error!("history: {}", e);
+            1
+        }
+    }
+}
+
+fn cpu_threads(matches: &ArgMatches, cfg: &Cfg) -> usize {
+    match value_t!(matches, "cpu threads", usize) {
+        Ok(0) | Err(_) => cfg.cpu_threads,
+        Ok(cpu_threads) => cpu_threads,
+    }
+}
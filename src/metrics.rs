@@ -0,0 +1,215 @@
+//! Counters and gauges in the Prometheus text format, served on `/metrics` by the status server.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Counter,
+    Gauge,
+    // a sum and a count, without quantiles
+    Summary,
+}
+
+/// A metric, its samples are told apart by their labels.
+pub struct Metric {
+    name: &'static str,
+    help: &'static str,
+    kind: Kind,
+}
+
+pub static ROUNDS_STARTED: Metric = Metric {
+    name: "scavenger_rounds_started_total",
+    help: "Rounds started by a new block.",
+    kind: Kind::Counter,
+};
+pub static ROUNDS_FINISHED: Metric = Metric {
+    name: "scavenger_rounds_finished_total",
+    help: "Rounds with all drives read.",
+    kind: Kind::Counter,
+};
+pub static ROUNDS_INTERRUPTED: Metric = Metric {
+    name: "scavenger_rounds_interrupted_total",
+    help: "Rounds abandoned for a new block before all drives were read.",
+    kind: Kind::Counter,
+};
+pub static ROUND_DURATION: Metric = Metric {
+    name: "scavenger_round_duration_seconds",
+    help: "Time it took to read all drives in the last finished round.",
+    kind: Kind::Gauge,
+};
+pub static DRIVE_READ_BYTES: Metric = Metric {
+    name: "scavenger_drive_read_bytes_total",
+    help: "Bytes of scoops read.",
+    kind: Kind::Counter,
+};
+pub static PLOT_READ_ERRORS: Metric = Metric {
+    name: "scavenger_plot_read_errors_total",
+    help: "Reads that failed, the rest of the plot is skipped for the round.",
+    kind: Kind::Counter,
+};
+pub static HASHED_BYTES: Metric = Metric {
+    name: "scavenger_hashed_bytes_total",
+    help: "Bytes of scoops hashed.",
+    kind: Kind::Counter,
+};
+pub static SUBMISSIONS: Metric = Metric {
+    name: "scavenger_submissions_total",
+    help: "Submissions by how the upstream answered, busy and failed ones are retried.",
+    kind: Kind::Counter,
+};
+pub static MINING_INFO_DURATION: Metric = Metric {
+    name: "scavenger_mining_info_request_duration_seconds",
+    help: "Time it took to get the mining info, failed requests included.",
+    kind: Kind::Summary,
+};
+pub static MINING_INFO_FAILURES: Metric = Metric {
+    name: "scavenger_mining_info_failures_total",
+    help: "Requests for the mining info that failed.",
+    kind: Kind::Counter,
+};
+
+static REGISTRY: Registry = Registry::new();
+
+impl Metric {
+    pub fn inc(&'static self, labels: &[(&str, &str)]) {
+        REGISTRY.update(self, labels, |sample| sample.value += 1.0);
+    }
+
+    pub fn add(&'static self, labels: &[(&str, &str)], value: f64) {
+        REGISTRY.update(self, labels, |sample| sample.value += value);
+    }
+
+    pub fn set(&'static self, labels: &[(&str, &str)], value: f64) {
+        REGISTRY.update(self, labels, |sample| sample.value = value);
+    }
+
+    pub fn observe(&'static self, labels: &[(&str, &str)], value: f64) {
+        REGISTRY.update(self, labels, |sample| {
+            sample.value += value;
+            sample.count += 1;
+        });
+    }
+}
+
+#[derive(Default)]
+struct Sample {
+    value: f64,
+    count: u64,
+}
+
+struct Family {
+    metric: &'static Metric,
+    // by rendered labels
+    samples: BTreeMap<String, Sample>,
+}
+
+struct Registry {
+    families: Mutex<BTreeMap<&'static str, Family>>,
+}
+
+impl Registry {
+    const fn new() -> Registry {
+        Registry {
+            families: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn update(
+        &self,
+        metric: &'static Metric,
+        labels: &[(&str, &str)],
+        f: impl FnOnce(&mut Sample),
+    ) {
+        let mut families = self.families.lock().unwrap();
+        let family = families.entry(metric.name).or_insert_with(|| Family {
+            metric,
+            samples: BTreeMap::new(),
+        });
+        f(family.samples.entry(render_labels(labels)).or_default());
+    }
+
+    fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut out = String::new();
+        for family in families.values() {
+            let metric = family.metric;
+            let kind = match metric.kind {
+                Kind::Counter => "counter",
+                Kind::Gauge => "gauge",
+                Kind::Summary => "summary",
+            };
+            let _ = writeln!(out, "# HELP {} {}", metric.name, metric.help);
+            let _ = writeln!(out, "# TYPE {} {}", metric.name, kind);
+            for (labels, sample) in &family.samples {
+                if metric.kind == Kind::Summary {
+                    let _ = writeln!(out, "{}_sum{} {}", metric.name, labels, sample.value);
+                    let _ = writeln!(out, "{}_count{} {}", metric.name, labels, sample.count);
+                } else {
+                    let _ = writeln!(out, "{}{} {}", metric.name, labels, sample.value);
+                }
+            }
+        }
+        out
+    }
+}
+
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+/// All metrics recorded so far.
+pub fn render() -> String {
+    REGISTRY.render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let registry = Registry::new();
+        for _ in 0..2 {
+            let labels = [("account", "1"), ("outcome", "accepted")];
+            registry.update(&SUBMISSIONS, &labels, |s| s.value += 1.0);
+        }
+        registry.update(&PLOT_READ_ERRORS, &[("plot", "a\"b")], |s| s.value += 1.0);
+        registry.update(&MINING_INFO_DURATION, &[("chain", "0")], |s| {
+            s.value += 0.5;
+            s.count += 1;
+        });
+        assert_eq!(
+            registry.render(),
+            "# HELP scavenger_mining_info_request_duration_seconds Time it took to get the \
+             mining info, failed requests included.\n\
+             # TYPE scavenger_mining_info_request_duration_seconds summary\n\
+             scavenger_mining_info_request_duration_seconds_sum{chain=\"0\"} 0.5\n\
+             scavenger_mining_info_request_duration_seconds_count{chain=\"0\"} 1\n\
+             # HELP scavenger_plot_read_errors_total Reads that failed, the rest of the plot is \
+             skipped for the round.\n\
+             # TYPE scavenger_plot_read_errors_total counter\n\
+             scavenger_plot_read_errors_total{plot=\"a\\\"b\"} 1\n\
+             # HELP scavenger_submissions_total Submissions by how the upstream answered, busy \
+             and failed ones are retried.\n\
+             # TYPE scavenger_submissions_total counter\n\
+             scavenger_submissions_total{account=\"1\",outcome=\"accepted\"} 2\n"
+        );
+    }
+}
@@ -1,29 +1,37 @@
+use crate::account::Account;
 use crate::com::api::MiningInfoResponse as MiningInfo;
 use crate::config::Cfg;
 use crate::cpu_worker::create_cpu_worker_task;
+use crate::dashboard;
 use crate::future::interval::Interval;
 #[cfg(feature = "opencl")]
 use crate::gpu_worker::create_gpu_worker_task;
 #[cfg(feature = "opencl")]
 use crate::gpu_worker_async::create_gpu_worker_task_async;
+use crate::history::{self, RoundRecord};
+use crate::metrics;
 #[cfg(feature = "opencl")]
 use crate::ocl::GpuBuffer;
 #[cfg(feature = "opencl")]
 use crate::ocl::GpuContext;
-use crate::plot::{Plot, SCOOP_SIZE};
+use crate::plot::{Meta, Plot, SCOOP_SIZE};
+use crate::plot_index::{mtime, PlotIndex};
 use crate::poc_hashing;
+use crate::proxy::Proxy;
 use crate::reader::Reader;
 use crate::requests::RequestHandler;
-use crate::utils::{get_device_id, new_thread_pool};
+use crate::status::Status;
+use crate::utils::{is_rotational, new_thread_pool};
 use crossbeam_channel;
-use filetime::FileTime;
 use futures::sync::mpsc;
 #[cfg(feature = "opencl")]
 use ocl_core::Mem;
 use std::cmp::{max, min};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::read_dir;
-use std::path::PathBuf;
+use std::iter;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -32,21 +40,33 @@ use std::u64;
 use stopwatch::Stopwatch;
 use tokio::prelude::*;
 use tokio::runtime::TaskExecutor;
+use url::Url;
 
 pub struct Miner {
     reader: Reader,
-    request_handler: RequestHandler,
+    chains: Vec<Chain>,
     rx_nonce_data: mpsc::Receiver<NonceData>,
     target_deadline: u64,
     account_id_to_target_deadline: HashMap<u64, u64>,
-    state: Arc<Mutex<State>>,
-    reader_task_count: usize,
+    plot_scanner: PlotScanner,
+    rescan_plot_dirs: bool,
+    proxy: Option<(SocketAddr, Arc<Proxy>)>,
+    status_listen_address: Option<SocketAddr>,
+    show_dashboard: bool,
     get_mining_info_interval: u64,
     executor: TaskExecutor,
     wakeup_after: i64,
 }
 
+/// A chain followed by the miner, the first one is the chain of `url`.
+struct Chain {
+    request_handler: RequestHandler,
+    state: Arc<Mutex<State>>,
+}
+
 pub struct State {
+    // set for all but the first chain
+    name: Option<String>,
     generation_signature: String,
     generation_signature_bytes: [u8; 32],
     height: u64,
@@ -56,15 +76,22 @@ pub struct State {
     base_target: u64,
     sw: Stopwatch,
     scanning: bool,
+    // of the last finished round
+    round_time: i64,
+    // unix time in seconds
+    round_started: u64,
     processed_reader_tasks: usize,
     scoop: u32,
     first: bool,
     outage: bool,
+    reader_task_count: usize,
+    total_size: u64,
 }
 
 impl State {
-    fn new() -> Self {
+    fn new(name: Option<String>, reader_task_count: usize, total_size: u64) -> Self {
         Self {
+            name,
             generation_signature: "".to_owned(),
             height: 0,
             block: 0,
@@ -76,8 +103,12 @@ impl State {
             sw: Stopwatch::new(),
             generation_signature_bytes: [0; 32],
             scanning: false,
+            round_time: 0,
+            round_started: 0,
             first: true,
             outage: false,
+            reader_task_count,
+            total_size,
         }
     }
 
@@ -97,15 +128,136 @@ impl State {
         let scoop =
             poc_hashing::calculate_scoop(mining_info.height, &self.generation_signature_bytes);
         info!(
+            height = mining_info.height,
+            scoop = scoop;
             "{: <80}",
-            format!("new block: height={}, scoop={}", mining_info.height, scoop)
+            format!(
+                "new block: {}height={}, scoop={}",
+                self.chain_label(),
+                mining_info.height,
+                scoop
+            )
         );
         self.scoop = scoop;
 
         self.sw.restart();
+        self.round_started = history::now();
         self.processed_reader_tasks = 0;
         self.scanning = true;
     }
+
+    /// The round as recorded in the history once it finished or was interrupted.
+    fn round_record(&self, chain: usize, bytes_scanned: u64) -> RoundRecord {
+        let (finished, interrupted) = if self.scanning {
+            (None, Some(history::now()))
+        } else {
+            (Some(history::now()), None)
+        };
+        RoundRecord {
+            chain,
+            name: self.name.clone(),
+            height: self.height,
+            generation_signature: self.generation_signature.clone(),
+            scoop: self.scoop,
+            base_target: self.base_target,
+            started: self.round_started,
+            finished,
+            interrupted,
+            round_time_ms: self.sw.elapsed_ms(),
+            bytes_scanned,
+        }
+    }
+
+    /// The round as reported by the status server, `chain` is the index of the chain.
+    pub fn status(&self, chain: usize, upstream: &Url) -> RoundStatus {
+        let mut best_deadlines: Vec<BestDeadline> = self
+            .account_id_to_best_deadline
+            .iter()
+            .filter(|(_, &deadline)| deadline != u64::MAX)
+            .map(|(&account_id, &deadline)| BestDeadline {
+                account_id,
+                account: Account(account_id).to_string(),
+                deadline,
+            })
+            .collect();
+        best_deadlines.sort_by_key(|best_deadline| best_deadline.account_id);
+        RoundStatus {
+            chain,
+            name: self.name.clone(),
+            upstream: upstream.to_string(),
+            height: self.height,
+            scoop: self.scoop,
+            base_target: self.base_target,
+            generation_signature: self.generation_signature.clone(),
+            target_deadline: self.server_target_deadline,
+            best_deadlines,
+            scanning: self.scanning,
+            round_time_ms: if self.scanning {
+                self.sw.elapsed_ms()
+            } else {
+                self.round_time
+            },
+            reader_tasks: self.reader_task_count,
+            processed_reader_tasks: self.processed_reader_tasks,
+            capacity_bytes: self.total_size * 4096,
+            outage: self.outage,
+        }
+    }
+
+    // names the chain in logs, unless it is the first one
+    fn chain_label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("chain={}, ", name),
+            None => String::new(),
+        }
+    }
+}
+
+/// The current round of a chain, `round_time_ms` is the time so far while scanning.
+#[derive(Serialize)]
+pub struct RoundStatus {
+    pub chain: usize,
+    pub name: Option<String>,
+    pub upstream: String,
+    pub height: u64,
+    pub scoop: u32,
+    pub base_target: u64,
+    pub generation_signature: String,
+    pub target_deadline: u64,
+    pub best_deadlines: Vec<BestDeadline>,
+    pub scanning: bool,
+    pub round_time_ms: i64,
+    pub reader_tasks: usize,
+    pub processed_reader_tasks: usize,
+    pub capacity_bytes: u64,
+    pub outage: bool,
+}
+
+#[derive(Serialize)]
+pub struct BestDeadline {
+    pub account_id: u64,
+    pub account: String,
+    pub deadline: u64,
+}
+
+/// A plot as listed by the status server.
+#[derive(Serialize)]
+pub struct PlotStatus {
+    pub drive: String,
+    pub account: String,
+    #[serde(flatten)]
+    pub meta: Meta,
+}
+
+/// The plots of a scanner as of its latest scan, shared with the status server so that it
+/// doesn't have to wait for the scanner.
+#[derive(Clone, Default)]
+pub struct ScannedPlots(Arc<Mutex<Arc<Vec<PlotStatus>>>>);
+
+impl ScannedPlots {
+    pub fn get(&self) -> Arc<Vec<PlotStatus>> {
+        self.0.lock().unwrap().clone()
+    }
 }
 
 pub struct NonceData {
@@ -116,6 +268,7 @@ pub struct NonceData {
     pub nonce: u64,
     pub reader_task_processed: bool,
     pub account_id: u64,
+    pub chain: usize,
 }
 
 pub trait Buffer {
@@ -167,68 +320,283 @@ impl Buffer for CpuBuffer {
     }
 }
 
-fn scan_plots(
-    plot_dirs: &[PathBuf],
+// reader tasks per drive for ssds if not configured
+const NON_ROTATIONAL_READER_TASKS: usize = 4;
+
+struct ScannedPlot {
+    drive_id: String,
+    meta: Meta,
+    mtime: i64,
+    size: u64,
+    plot: Arc<Mutex<Plot>>,
+}
+
+/// Keeps track of the plots in the plot dirs, so that plots can be added and removed between
+/// rounds without restarting.
+pub struct PlotScanner {
+    plot_dirs: Vec<PathBuf>,
     use_direct_io: bool,
     dummy: bool,
-) -> (HashMap<String, Arc<Vec<Mutex<Plot>>>>, u64) {
-    let mut drive_id_to_plots: HashMap<String, Vec<Mutex<Plot>>> = HashMap::new();
-    let mut global_capacity: u64 = 0;
-
-    for plot_dir in plot_dirs {
-        let mut num_plots = 0;
-        let mut local_capacity: u64 = 0;
-        for file in read_dir(plot_dir).unwrap() {
-            let file = &file.unwrap().path();
-
-            if let Ok(p) = Plot::new(file, use_direct_io, dummy) {
-                let drive_id = get_device_id(&file.to_str().unwrap().to_string());
-                let plots = drive_id_to_plots.entry(drive_id).or_insert(Vec::new());
-
-                local_capacity += p.meta.nonces as u64;
-                plots.push(Mutex::new(p));
-                num_plots += 1;
+    reader_tasks_per_drive: usize,
+    plots: HashMap<PathBuf, ScannedPlot>,
+    // reader tasks of every drive, determined when its first plot is added
+    drive_reader_tasks: HashMap<String, usize>,
+    index: PlotIndex,
+    scanned_plots: ScannedPlots,
+}
+
+impl PlotScanner {
+    pub fn new(
+        plot_dirs: &[PathBuf],
+        index_path: Option<&Path>,
+        use_direct_io: bool,
+        dummy: bool,
+        reader_tasks_per_drive: usize,
+    ) -> PlotScanner {
+        let mut scanner = PlotScanner {
+            plot_dirs: plot_dirs.to_vec(),
+            use_direct_io,
+            dummy,
+            reader_tasks_per_drive,
+            plots: HashMap::new(),
+            drive_reader_tasks: HashMap::new(),
+            index: PlotIndex::load(index_path),
+            scanned_plots: ScannedPlots::default(),
+        };
+        scanner.scan(true);
+        scanner
+    }
+
+    /// Adds new plots and drops removed ones, returns whether anything changed.
+    pub fn scan(&mut self, initial: bool) -> bool {
+        let mut seen = HashSet::new();
+        let mut changed = false;
+        for plot_dir in &self.plot_dirs {
+            let mut num_plots = 0;
+            let mut local_capacity: u64 = 0;
+            let entries = match read_dir(plot_dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    if initial {
+                        error!("can't read {}: {}", plot_dir.to_str().unwrap(), e);
+                    }
+                    continue;
+                }
+            };
+            for file in entries {
+                let file = match file {
+                    Ok(file) => file.path(),
+                    Err(_) => continue,
+                };
+
+                let metadata = match file.metadata() {
+                    Ok(metadata) => metadata,
+                    Err(_) => continue,
+                };
+
+                // a plot replaced at the same path is added anew
+                if let Some(p) = self.plots.get(&file) {
+                    if p.mtime == mtime(&metadata) && p.size == metadata.len() {
+                        seen.insert(file);
+                        local_capacity += p.meta.nonces;
+                        num_plots += 1;
+                        continue;
+                    }
+                    let p = self.plots.remove(&file).unwrap();
+                    info!("{: <80}", format!("plot changed: file={}", p.meta.name));
+                    changed = true;
+                }
+                let p = match self.index.get(&file, &metadata) {
+                    Some(entry) => Plot::with_sector_size(
+                        &file,
+                        entry.sector_size,
+                        self.use_direct_io,
+                        self.dummy,
+                    ),
+                    None => Plot::new(&file, self.use_direct_io, self.dummy),
+                };
+                if let Ok(p) = p {
+                    let drive_id = match self.index.get(&file, &metadata) {
+                        Some(entry) => entry.drive_id.clone(),
+                        None => match self.index.insert(&file, &metadata, p.sector_size()) {
+                            Ok(entry) => entry.drive_id.clone(),
+                            Err(e) => {
+                                error!("can't determine drive of {}: {}", p.meta.name, e);
+                                continue;
+                            }
+                        },
+                    };
+                    if !self.drive_reader_tasks.contains_key(&drive_id) {
+                        let reader_tasks = match self.reader_tasks_per_drive {
+                            0 => match is_rotational(&p.path) {
+                                Ok(false) => NON_ROTATIONAL_READER_TASKS,
+                                _ => 1,
+                            },
+                            n => n,
+                        };
+                        self.drive_reader_tasks
+                            .insert(drive_id.clone(), reader_tasks);
+                    }
+                    if !initial {
+                        info!("{: <80}", format!("plot added: file={}", p.meta.name));
+                        for other in self.plots.values() {
+                            if other.meta.account_id == p.meta.account_id {
+                                other.meta.overlaps_with(&p.meta);
+                            }
+                        }
+                    }
+                    local_capacity += p.meta.nonces;
+                    num_plots += 1;
+                    self.plots.insert(
+                        file.clone(),
+                        ScannedPlot {
+                            drive_id,
+                            meta: p.meta.clone(),
+                            mtime: mtime(&metadata),
+                            size: metadata.len(),
+                            plot: Arc::new(Mutex::new(p)),
+                        },
+                    );
+                    seen.insert(file);
+                    changed = true;
+                }
+            }
+
+            if initial {
+                info!(
+                    "path={}, files={}, size={:.4} TiB",
+                    plot_dir.to_str().unwrap(),
+                    num_plots,
+                    local_capacity as f64 / 4.0 / 1024.0 / 1024.0
+                );
+                if num_plots == 0 {
+                    warn!("no plots in {}", plot_dir.to_str().unwrap());
+                }
             }
         }
 
-        info!(
-            "path={}, files={}, size={:.4} TiB",
-            plot_dir.to_str().unwrap(),
-            num_plots,
-            local_capacity as f64 / 4.0 / 1024.0 / 1024.0
-        );
+        let removed: Vec<PathBuf> = self
+            .plots
+            .keys()
+            .filter(|path| !seen.contains(*path))
+            .cloned()
+            .collect();
+        for path in removed {
+            let p = self.plots.remove(&path).unwrap();
+            info!("{: <80}", format!("plot removed: file={}", p.meta.name));
+            changed = true;
+        }
 
-        global_capacity += local_capacity;
-        if num_plots == 0 {
-            warn!("no plots in {}", plot_dir.to_str().unwrap());
+        self.index.retain(&seen);
+        if let Err(e) = self.index.save() {
+            warn!("can't save plot index: {}", e);
         }
+
+        if initial || changed {
+            *self.scanned_plots.0.lock().unwrap() = Arc::new(self.plot_status());
+            let drives: HashSet<&String> = self.plots.values().map(|p| &p.drive_id).collect();
+            info!(
+                "{: <80}",
+                format!(
+                    "plot files loaded: total drives={}, total capacity={:.4} TiB",
+                    drives.len(),
+                    self.total_size() as f64 / 64.0 / 4.0 / 1024.0 / 1024.0
+                )
+            );
+        }
+        changed
     }
 
-    // sort plots by filetime and get them into an arc
-    let drive_id_to_plots: HashMap<String, Arc<Vec<Mutex<Plot>>>> = drive_id_to_plots
-        .drain()
-        .map(|(drive_id, mut plots)| {
-            plots.sort_by_key(|p| {
-                let m = p.lock().unwrap().fh.metadata().unwrap();
-                -FileTime::from_last_modification_time(&m).unix_seconds()
-            });
-            (drive_id, Arc::new(plots))
-        })
-        .collect();
+    /// Groups the plots by drive, every drive's plots sorted by filetime, newest first. The
+    /// plots of drives with several reader tasks are dealt out to groups of their own that are
+    /// read in parallel.
+    pub fn drive_id_to_plots(&self) -> HashMap<String, Arc<Vec<Arc<Mutex<Plot>>>>> {
+        self.groups()
+            .into_iter()
+            .map(|(drive_id, plots)| {
+                let plots = plots.iter().map(|p| p.plot.clone()).collect();
+                (drive_id, Arc::new(plots))
+            })
+            .collect()
+    }
 
-    info!(
-        "plot files loaded: total drives={}, total capacity={:.4} TiB",
-        drive_id_to_plots.len(),
-        global_capacity as f64 / 4.0 / 1024.0 / 1024.0
-    );
+    /// The scoop bytes of every group of `drive_id_to_plots`.
+    pub fn drive_bytes(&self) -> HashMap<String, u64> {
+        self.groups()
+            .into_iter()
+            .map(|(drive_id, plots)| {
+                let nonces: u64 = plots.iter().map(|p| p.meta.nonces).sum();
+                (drive_id, nonces * 64)
+            })
+            .collect()
+    }
 
-    (drive_id_to_plots, global_capacity * 64)
+    fn groups(&self) -> HashMap<String, Vec<&ScannedPlot>> {
+        let mut drive_id_to_plots: HashMap<String, Vec<&ScannedPlot>> = HashMap::new();
+        for p in self.plots.values() {
+            drive_id_to_plots
+                .entry(p.drive_id.clone())
+                .or_default()
+                .push(p);
+        }
+        let mut groups = HashMap::new();
+        for (drive_id, mut plots) in drive_id_to_plots.drain() {
+            plots.sort_by_key(|p| -p.mtime);
+            let reader_tasks = min(self.drive_reader_tasks[&drive_id], plots.len());
+            if reader_tasks == 1 {
+                groups.insert(drive_id, plots);
+                continue;
+            }
+            for i in 0..reader_tasks {
+                let plots = plots
+                    .iter()
+                    .skip(i)
+                    .step_by(reader_tasks)
+                    .cloned()
+                    .collect();
+                groups.insert(format!("{}-{}", drive_id, i), plots);
+            }
+        }
+        groups
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.plots.values().map(|p| p.meta.nonces).sum::<u64>() * 64
+    }
+
+    /// The plots as of the latest scan, updated by every scan that changed something.
+    pub fn scanned_plots(&self) -> ScannedPlots {
+        self.scanned_plots.clone()
+    }
+
+    // all plots known, sorted by drive and name
+    fn plot_status(&self) -> Vec<PlotStatus> {
+        let mut plots: Vec<PlotStatus> = self
+            .plots
+            .values()
+            .map(|p| PlotStatus {
+                drive: p.drive_id.clone(),
+                account: Account(p.meta.account_id).to_string(),
+                meta: p.meta.clone(),
+            })
+            .collect();
+        plots.sort_by(|a, b| (&a.drive, &a.meta.name).cmp(&(&b.drive, &b.meta.name)));
+        plots
+    }
 }
 
 impl Miner {
     pub fn new(cfg: Cfg, executor: TaskExecutor) -> Miner {
-        let (drive_id_to_plots, total_size) =
-            scan_plots(&cfg.plot_dirs, cfg.hdd_use_direct_io, cfg.benchmark_cpu());
+        let plot_scanner = PlotScanner::new(
+            &cfg.plot_dirs,
+            cfg.plot_index_file.as_deref(),
+            cfg.hdd_use_direct_io,
+            cfg.benchmark_cpu(),
+            cfg.hdd_reader_tasks_per_drive,
+        );
+        let drive_id_to_plots = plot_scanner.drive_id_to_plots();
+        let drive_bytes = plot_scanner.drive_bytes();
+        let total_size = plot_scanner.total_size();
 
         let cpu_threads = cfg.cpu_threads;
         let cpu_worker_task_count = cfg.cpu_worker_task_count;
@@ -395,7 +763,6 @@ impl Miner {
                         tx_empty_buffers.clone(),
                         tx_nonce_data.clone(),
                         gpu_contexts[i].clone(),
-                        drive_id_to_plots.len(),
                     )
                 });
             } else {
@@ -417,34 +784,78 @@ impl Miner {
         #[cfg(not(feature = "opencl"))]
         let tx_read_replies_gpu = None;
 
-        Miner {
-            reader_task_count: drive_id_to_plots.len(),
-            reader: Reader::new(
-                drive_id_to_plots,
-                total_size,
-                reader_thread_count,
-                rx_empty_buffers,
-                tx_empty_buffers,
-                tx_read_replies_cpu,
-                tx_read_replies_gpu,
-                cfg.show_progress,
-                cfg.show_drive_stats,
-                cfg.cpu_thread_pinning,
-                cfg.benchmark_cpu(),
-            ),
-            rx_nonce_data,
-            target_deadline: cfg.target_deadline,
-            account_id_to_target_deadline: cfg.account_id_to_target_deadline,
+        let reader_task_count = drive_id_to_plots.len();
+        let rescan_plot_dirs = cfg.rescan_plot_dirs && !cfg.benchmark_cpu();
+
+        let reader = Reader::new(
+            drive_id_to_plots,
+            drive_bytes,
+            reader_thread_count,
+            rx_empty_buffers,
+            tx_empty_buffers,
+            tx_read_replies_cpu,
+            tx_read_replies_gpu,
+            cfg.show_progress,
+            cfg.show_drive_stats,
+            cfg.cpu_thread_pinning,
+            cfg.benchmark_cpu(),
+            cfg.hdd_io_uring_queue_depth,
+        );
+
+        let total_size_gb = (total_size * 4 / 1024 / 1024) as usize;
+        let mut chains = vec![Chain {
             request_handler: RequestHandler::new(
-                cfg.url,
-                cfg.account_id_to_secret_phrase,
+                iter::once(cfg.url).chain(cfg.fallback_urls).collect(),
+                cfg.account_id_to_secret_phrase.clone(),
+                cfg.secret_phrase_in_query,
                 cfg.timeout,
-                (total_size * 4 / 1024 / 1024) as usize,
+                total_size_gb,
                 cfg.send_proxy_details,
                 cfg.additional_headers,
+                cfg.account_id_to_upstream,
                 executor.clone(),
             ),
-            state: Arc::new(Mutex::new(State::new())),
+            state: Arc::new(Mutex::new(State::new(None, reader_task_count, total_size))),
+        }];
+        for chain in cfg.chains {
+            info!("chain: name={}, url={}", chain.name, chain.url);
+            chains.push(Chain {
+                request_handler: RequestHandler::new(
+                    iter::once(chain.url).chain(chain.fallback_urls).collect(),
+                    cfg.account_id_to_secret_phrase.clone(),
+                    cfg.secret_phrase_in_query,
+                    cfg.timeout,
+                    total_size_gb,
+                    cfg.send_proxy_details,
+                    chain.additional_headers,
+                    HashMap::new(),
+                    executor.clone(),
+                ),
+                state: Arc::new(Mutex::new(State::new(
+                    Some(chain.name),
+                    reader_task_count,
+                    total_size,
+                ))),
+            });
+        }
+
+        // downstream miners get the mining info of the first chain and submit to it
+        let proxy = cfg.proxy_listen_address.map(|addr| {
+            let proxy = Proxy::new(chains[0].request_handler.clone(), total_size_gb);
+            (addr, Arc::new(proxy))
+        });
+
+        Miner {
+            reader,
+            chains,
+            rx_nonce_data,
+            target_deadline: cfg.target_deadline,
+            account_id_to_target_deadline: cfg.account_id_to_target_deadline,
+            plot_scanner,
+            rescan_plot_dirs,
+            proxy,
+            status_listen_address: cfg.status_listen_address,
+            show_dashboard: cfg.show_dashboard,
             // floor at 1s to protect servers
             get_mining_info_interval: max(1000, cfg.get_mining_info_interval),
             executor,
@@ -453,86 +864,211 @@ impl Miner {
     }
 
     pub fn run(self) {
-        let request_handler = self.request_handler.clone();
-        let total_size = self.reader.total_size;
+        let mut plot_scanner = self.plot_scanner;
 
         // TODO: this doesn't need to be arc mutex if we manage to separate
         // reader from miner so that we can simply move it
         let reader = Arc::new(Mutex::new(self.reader));
 
-        let state = self.state.clone();
-        // there might be a way to solve this without two nested moves
-        let get_mining_info_interval = self.get_mining_info_interval;
-        let wakeup_after = self.wakeup_after;
-        self.executor.clone().spawn(
-            Interval::new_interval(Duration::from_millis(get_mining_info_interval))
-                .for_each(move |_| {
-                    let state = state.clone();
-                    let reader = reader.clone();
-                    request_handler.get_mining_info().then(move |mining_info| {
-                        match mining_info {
-                            Ok(mining_info) => {
-                                let mut state = state.lock().unwrap();
-                                state.first = false;
-                                if state.outage {
-                                    error!("{: <80}", "outage resolved.");
-                                    state.outage = false;
-                                }
-                                if mining_info.generation_signature != state.generation_signature {
-                                    state.update_mining_info(&mining_info);
-
-                                    reader.lock().unwrap().start_reading(
-                                        mining_info.height,
-                                        state.block,
-                                        mining_info.base_target,
-                                        state.scoop,
-                                        &Arc::new(state.generation_signature_bytes),
-                                    );
-                                    drop(state);
-                                } else if !state.scanning
-                                    && wakeup_after != 0
-                                    && state.sw.elapsed_ms() > wakeup_after
-                                {
-                                    info!("HDD, wakeup!");
-                                    reader.lock().unwrap().wakeup();
-                                    state.sw.restart();
-                                }
-                            }
-                            _ => {
-                                let mut state = state.lock().unwrap();
-                                if state.first {
-                                    error!(
-                                        "{: <80}",
-                                        "error getting mining info, please check server config"
-                                    );
+        // the capacity changes for all chains when one of them rescans the plots
+        let request_handlers: Vec<RequestHandler> = self
+            .chains
+            .iter()
+            .map(|chain| chain.request_handler.clone())
+            .collect();
+
+        let executor = self.executor.clone();
+        let proxy = self.proxy.map(|(addr, proxy)| {
+            Proxy::serve(proxy.clone(), addr, &executor);
+            proxy
+        });
+
+        let status = Arc::new(Status::new(
+            self.chains
+                .iter()
+                .map(|chain| (chain.state.clone(), chain.request_handler.clone()))
+                .collect(),
+            reader.lock().unwrap().drives(),
+            plot_scanner.scanned_plots(),
+        ));
+        if let Some(addr) = self.status_listen_address {
+            Status::serve(status.clone(), addr, &executor);
+        }
+        if self.show_dashboard {
+            dashboard::start(status);
+        }
+
+        // Scanning can take a while on slow drives, so it is done by a thread of its own when a
+        // new block arrives. Changes are read from the following round on.
+        let tx_rescan = if self.rescan_plot_dirs {
+            let (tx_rescan, rx_rescan) = crossbeam_channel::bounded(1);
+            let reader = reader.clone();
+            let request_handlers = request_handlers.clone();
+            let proxy = proxy.clone();
+            thread::spawn(move || {
+                for () in rx_rescan {
+                    if !plot_scanner.scan(false) {
+                        continue;
+                    }
+                    let drive_id_to_plots = plot_scanner.drive_id_to_plots();
+                    let drive_bytes = plot_scanner.drive_bytes();
+                    let total_size = plot_scanner.total_size();
+                    for request_handler in &request_handlers {
+                        request_handler.set_total_size_gb((total_size * 4 / 1024 / 1024) as usize);
+                    }
+                    if let Some(proxy) = &proxy {
+                        proxy.set_local_size_gb((total_size * 4 / 1024 / 1024) as usize);
+                    }
+                    reader
+                        .lock()
+                        .unwrap()
+                        .update_plots(drive_id_to_plots, drive_bytes);
+                }
+            });
+            Some(tx_rescan)
+        } else {
+            None
+        };
+
+        // every chain follows its own mining info, they only share the reader
+        for (i, chain) in self.chains.iter().enumerate() {
+            let request_handler = chain.request_handler.clone();
+            let proxy = proxy.clone();
+            let state = chain.state.clone();
+            let reader = reader.clone();
+            let tx_rescan = tx_rescan.clone();
+            // there might be a way to solve this without two nested moves
+            let get_mining_info_interval = self.get_mining_info_interval;
+            let wakeup_after = self.wakeup_after;
+            self.executor.clone().spawn(
+                Interval::new_interval(Duration::from_millis(get_mining_info_interval))
+                    .for_each(move |_| {
+                        let state = state.clone();
+                        let reader = reader.clone();
+                        let tx_rescan = tx_rescan.clone();
+                        let request_handler_inner = request_handler.clone();
+                        let proxy = proxy.clone();
+                        let sw = Stopwatch::start_new();
+                        request_handler.get_mining_info().then(move |mining_info| {
+                            let chain = i.to_string();
+                            metrics::MINING_INFO_DURATION
+                                .observe(&[("chain", &chain)], sw.elapsed_ms() as f64 / 1000.0);
+                            match mining_info {
+                                Ok(mining_info) => {
+                                    let mut state = state.lock().unwrap();
                                     state.first = false;
-                                    state.outage = true;
-                                } else {
-                                    if !state.outage {
+                                    if state.outage {
+                                        error!(
+                                            "{: <80}",
+                                            format!(
+                                                "outage resolved, {}upstream={}",
+                                                state.chain_label(),
+                                                request_handler_inner.active_upstream()
+                                            )
+                                        );
+                                        state.outage = false;
+                                    }
+                                    if mining_info.generation_signature
+                                        != state.generation_signature
+                                    {
+                                        if state.scanning {
+                                            metrics::ROUNDS_INTERRUPTED.inc(&[("chain", &chain)]);
+                                            let bytes_scanned = reader
+                                                .lock()
+                                                .unwrap()
+                                                .drive_progress()
+                                                .iter()
+                                                .filter(|p| {
+                                                    p.chain == i && p.height == state.height
+                                                })
+                                                .map(|p| p.bytes_read)
+                                                .sum();
+                                            history::record_round(
+                                                &state.round_record(i, bytes_scanned),
+                                            );
+                                        }
+                                        metrics::ROUNDS_STARTED.inc(&[("chain", &chain)]);
+                                        state.update_mining_info(&mining_info);
+                                        if let Some(proxy) = proxy.as_ref().filter(|_| i == 0) {
+                                            proxy.update_mining_info(
+                                                &mining_info,
+                                                state.block,
+                                                state.generation_signature_bytes,
+                                            );
+                                        }
+
+                                        // requests made while a scan is pending are merged into it
+                                        if let Some(tx_rescan) = &tx_rescan {
+                                            let _ = tx_rescan.try_send(());
+                                        }
+                                        let mut reader = reader.lock().unwrap();
+                                        // plots may have changed during another chain's round
+                                        state.reader_task_count = reader.drive_count();
+                                        state.total_size = reader.total_size;
+                                        reader.start_reading(
+                                            i,
+                                            mining_info.height,
+                                            state.block,
+                                            mining_info.base_target,
+                                            state.scoop,
+                                            &Arc::new(state.generation_signature_bytes),
+                                        );
+                                        drop(state);
+                                    } else if !state.scanning
+                                        && wakeup_after != 0
+                                        && state.sw.elapsed_ms() > wakeup_after
+                                    {
+                                        info!("HDD, wakeup!");
+                                        reader.lock().unwrap().wakeup();
+                                        state.sw.restart();
+                                    }
+                                }
+                                _ => {
+                                    metrics::MINING_INFO_FAILURES.inc(&[("chain", &chain)]);
+                                    let mut state = state.lock().unwrap();
+                                    if state.first {
                                         error!(
                                             "{: <80}",
-                                            "error getting mining info => connection outage..."
+                                            format!(
+                                                "error getting mining info, please check server \
+                                                 config, {}upstream={}",
+                                                state.chain_label(),
+                                                request_handler_inner.active_upstream()
+                                            )
                                         );
+                                        state.first = false;
+                                        state.outage = true;
+                                    } else {
+                                        if !state.outage {
+                                            error!(
+                                                "{: <80}",
+                                                format!(
+                                                    "error getting mining info => connection \
+                                                     outage, {}upstream={}",
+                                                    state.chain_label(),
+                                                    request_handler_inner.active_upstream()
+                                                )
+                                            );
+                                        }
+                                        state.outage = true;
                                     }
-                                    state.outage = true;
                                 }
                             }
-                        }
-                        future::ok(())
+                            future::ok(())
+                        })
                     })
-                })
-                .map_err(|e| panic!("interval errored: err={:?}", e)),
-        );
+                    .map_err(|e| panic!("interval errored: err={:?}", e)),
+            );
+        }
 
         let target_deadline = self.target_deadline;
         let account_id_to_target_deadline = self.account_id_to_target_deadline;
-        let request_handler = self.request_handler.clone();
-        let state = self.state.clone();
-        let reader_task_count = self.reader_task_count;
+        let chains = self.chains;
         self.executor.clone().spawn(
             self.rx_nonce_data
                 .for_each(move |nonce_data| {
-                    let mut state = state.lock().unwrap();
+                    let chain = &chains[nonce_data.chain];
+                    let mut state = chain.state.lock().unwrap();
                     let deadline = nonce_data.deadline / nonce_data.base_target;
                     // FIX: burst became forky as hell, showing some weaknesses in Scavengers update mechanism.                    
                     if state.block == nonce_data.block {
@@ -552,7 +1088,7 @@ impl Miner {
                             state
                                 .account_id_to_best_deadline
                                 .insert(nonce_data.account_id, deadline);
-                            request_handler.submit_nonce(
+                            chain.request_handler.submit_nonce(
                                 nonce_data.account_id,
                                 nonce_data.nonce,
                                 nonce_data.height,
@@ -565,20 +1101,33 @@ impl Miner {
 
                         if nonce_data.reader_task_processed {
                             state.processed_reader_tasks += 1;
-                            if state.processed_reader_tasks == reader_task_count {
+                            if state.processed_reader_tasks == state.reader_task_count {
+                                let speed = state.total_size as f64 * 1000.0
+                                    / 1024.0
+                                    / 1024.0
+                                    / state.sw.elapsed_ms() as f64;
                                 info!(
+                                    height = state.height,
+                                    round_time_ms = state.sw.elapsed_ms(),
+                                    speed_mib_s = speed;
                                     "{: <80}",
                                     format!(
-                                        "round finished: roundtime={}ms, speed={:.2}MiB/s",
+                                        "round finished: {}roundtime={}ms, speed={:.2}MiB/s",
+                                        state.chain_label(),
                                         state.sw.elapsed_ms(),
-                                        total_size as f64 * 1000.0
-                                            / 1024.0
-                                            / 1024.0
-                                            / state.sw.elapsed_ms() as f64
+                                        speed
                                     )
                                 );
-                                state.sw.restart();
+                                state.round_time = state.sw.elapsed_ms();
+                                let chain = nonce_data.chain.to_string();
+                                metrics::ROUNDS_FINISHED.inc(&[("chain", &chain)]);
+                                metrics::ROUND_DURATION
+                                    .set(&[("chain", &chain)], state.round_time as f64 / 1000.0);
                                 state.scanning = false;
+                                history::record_round(
+                                    &state.round_record(nonce_data.chain, state.total_size),
+                                );
+                                state.sw.restart();
                             }
                         }
                     }
@@ -588,3 +1137,186 @@ impl Miner {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::com::mock::MockServer;
+    use crate::plot::NONCE_SIZE;
+    use std::env;
+    use std::fs;
+    use std::net::TcpListener;
+    use std::time::Instant;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_plot_scanner() {
+        let dir = env::temp_dir().join("scavenger_test_scanner");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("1_0_1"), vec![0u8; NONCE_SIZE as usize]).unwrap();
+
+        let mut scanner = PlotScanner::new(std::slice::from_ref(&dir), None, false, false, 1);
+        let scanned_plots = scanner.scanned_plots();
+        assert_eq!(scanner.total_size(), 64);
+        assert_eq!(scanned_plots.get().len(), 1);
+        assert!(!scanner.scan(false));
+
+        // incomplete plots are picked up once they have their full size
+        fs::write(dir.join("1_1_2"), vec![0u8; NONCE_SIZE as usize]).unwrap();
+        assert!(!scanner.scan(false));
+        fs::write(dir.join("1_1_2"), vec![0u8; 2 * NONCE_SIZE as usize]).unwrap();
+        assert!(scanner.scan(false));
+        assert_eq!(scanner.total_size(), 3 * 64);
+        assert_eq!(scanned_plots.get().len(), 2);
+
+        // a plot replaced at the same path is opened again
+        fs::write(dir.join("1_1_2"), vec![1u8; 2 * NONCE_SIZE as usize]).unwrap();
+        filetime::set_file_mtime(dir.join("1_1_2"), filetime::FileTime::from_unix_time(1, 0))
+            .unwrap();
+        assert!(scanner.scan(false));
+        assert!(!scanner.scan(false));
+        assert_eq!(scanner.total_size(), 3 * 64);
+        let drive_id_to_plots = scanner.drive_id_to_plots();
+        assert_eq!(
+            drive_id_to_plots.values().map(|p| p.len()).sum::<usize>(),
+            2
+        );
+
+        fs::remove_file(dir.join("1_0_1")).unwrap();
+        assert!(scanner.scan(false));
+        assert_eq!(scanner.total_size(), 2 * 64);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reader_tasks_per_drive() {
+        let dir = env::temp_dir().join("scavenger_test_reader_tasks");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for name in &["1_0_1", "1_1_1", "1_2_1"] {
+            fs::write(dir.join(name), vec![0u8; NONCE_SIZE as usize]).unwrap();
+        }
+
+        let scanner = PlotScanner::new(std::slice::from_ref(&dir), None, false, false, 2);
+        let drive_id_to_plots = scanner.drive_id_to_plots();
+        let mut lens: Vec<usize> = drive_id_to_plots.values().map(|p| p.len()).collect();
+        lens.sort();
+        assert_eq!(lens, vec![1, 2]);
+        let drive_bytes = scanner.drive_bytes();
+        for (drive_id, plots) in &drive_id_to_plots {
+            assert_eq!(drive_bytes[drive_id], plots.len() as u64 * 64);
+        }
+
+        // never more tasks than plots
+        let scanner = PlotScanner::new(std::slice::from_ref(&dir), None, false, false, 8);
+        assert_eq!(scanner.drive_id_to_plots().len(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // the best deadline of the test plot for the mock's block at `height`
+    fn best_deadline(height: u64) -> (u64, u64) {
+        let gensig = poc_hashing::decode_gensig(&format!("{:064x}", height));
+        let scoop = poc_hashing::calculate_scoop(height, &gensig) as usize;
+        let data = fs::read("test_data/10282355196851764065_0_8").unwrap();
+        let scoop_data =
+            &data[scoop * 8 * SCOOP_SIZE as usize..(scoop + 1) * 8 * SCOOP_SIZE as usize];
+        poc_hashing::find_best_deadline_rust(scoop_data, 8, &gensig)
+    }
+
+    #[test]
+    fn test_miner() {
+        let server = MockServer::start();
+        let status_addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let cfg: Cfg = serde_yaml::from_str(&format!(
+            "plot_dirs: ['test_data']\n\
+             plot_index_file: ~\n\
+             url: '{}'\n\
+             hdd_use_direct_io: false\n\
+             cpu_threads: 1\n\
+             cpu_worker_task_count: 1\n\
+             target_deadline: 18446744073709551615\n\
+             get_mining_info_interval: 1000\n\
+             status_listen_address: '{}'\n\
+             show_progress: false",
+            server.url(),
+            status_addr
+        ))
+        .unwrap();
+
+        let rt = Runtime::new().unwrap();
+        Miner::new(cfg, rt.executor()).run();
+
+        // the best deadline of the plot gets submitted every block
+        for height in 1..3 {
+            let submissions = server.wait_for_submissions(height as usize, Duration::from_secs(10));
+            assert_eq!(submissions.len(), height as usize);
+            let submission = &submissions[height as usize - 1];
+            assert_eq!(submission.account_id, 10282355196851764065);
+            assert_eq!(submission.height, height);
+            let (deadline, offset) = best_deadline(height);
+            assert_eq!(submission.nonce, offset);
+            assert_eq!(submission.deadline, Some(deadline));
+            server.new_block();
+        }
+
+        // the status server reports the round, the drives, the submissions and the plots
+        let get = |path: &str| -> serde_json::Value {
+            reqwest::get(&format!("http://{}{}", status_addr, path))
+                .unwrap()
+                .json()
+                .unwrap()
+        };
+        let timeout = Instant::now() + Duration::from_secs(10);
+        let recorded = || {
+            get("/submissions")[0]["submissions"]
+                .as_array()
+                .unwrap()
+                .len()
+        };
+        while recorded() < 2 {
+            assert!(Instant::now() < timeout);
+            thread::sleep(Duration::from_millis(10));
+        }
+        let submissions = get("/submissions");
+        let submission = submissions[0]["submissions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|submission| submission["height"] == 2)
+            .unwrap();
+        assert_eq!(submission["outcome"], "accepted");
+        assert_eq!(submission["deadline_pool"], best_deadline(2).0);
+
+        let round = &get("/round")[0];
+        assert_eq!(round["chain"], 0);
+        assert_eq!(round["upstream"], server.url().to_string());
+        assert!(round["height"].as_u64().unwrap() >= 2);
+
+        let drives = get("/drives");
+        assert_eq!(drives[0]["chain"], 0);
+        assert_eq!(drives[0]["plots"], 1);
+
+        let plots = get("/plots");
+        assert_eq!(plots.as_array().unwrap().len(), 1);
+        assert_eq!(plots[0]["account_id"], 10282355196851764065u64);
+        assert_eq!(plots[0]["nonces"], 8);
+        assert_eq!(get("/unknown")["error"]["message"], "unknown path");
+
+        let metrics = reqwest::get(&format!("http://{}/metrics", status_addr))
+            .unwrap()
+            .text()
+            .unwrap();
+        assert!(metrics.contains("scavenger_rounds_finished_total{chain=\"0\"}"));
+        assert!(metrics.contains(
+            "scavenger_submissions_total{account=\"10282355196851764065\",outcome=\"accepted\"}"
+        ));
+
+        rt.shutdown_now().wait().unwrap();
+    }
+}
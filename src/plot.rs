@@ -8,16 +8,20 @@ use std::io;
 use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 
-const SCOOPS_IN_NONCE: u64 = 4096;
-const SHABAL256_HASH_SIZE: u64 = 32;
+pub const SCOOPS_IN_NONCE: u64 = 4096;
+pub const SHABAL256_HASH_SIZE: u64 = 32;
 pub const SCOOP_SIZE: u64 = SHABAL256_HASH_SIZE * 2;
-const NONCE_SIZE: u64 = SCOOP_SIZE * SCOOPS_IN_NONCE;
+pub const NONCE_SIZE: u64 = SCOOP_SIZE * SCOOPS_IN_NONCE;
+// reads are split into chunks of this size to check for interrupts in between
+pub const READ_CHUNK_SIZE: u64 = 1024 * 1024;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize)]
 pub struct Meta {
     pub account_id: u64,
     pub start_nonce: u64,
     pub nonces: u64,
+    // PoC1 plots are stored in groups of `stagger` nonces, None for PoC2 plots
+    pub stagger: Option<u64>,
     pub name: String,
 }
 
@@ -45,15 +49,36 @@ pub struct Plot {
     pub meta: Meta,
     pub path: String,
     pub fh: File,
+    fh_buffered: Option<File>,
+    mirror: Vec<u8>,
     read_offset: u64,
     use_direct_io: bool,
     sector_size: u64,
     dummy: bool,
 }
 
+/// A chunk of a scoop: `direct_bytes` at `addr` are read with the (possibly direct io) file
+/// handle, the following `buffered_bytes` with the buffered one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReadPlan {
+    pub addr: u64,
+    pub direct_bytes: u64,
+    pub buffered_bytes: u64,
+    pub start_nonce: u64,
+    pub finished: bool,
+}
+
+impl ReadPlan {
+    pub fn len(&self) -> usize {
+        (self.direct_bytes + self.buffered_bytes) as usize
+    }
+}
+
 cfg_if! {
     if #[cfg(unix)] {
         use std::os::unix::fs::OpenOptionsExt;
+        #[cfg(all(feature = "io_uring", target_os = "linux"))]
+        use std::os::unix::io::{AsRawFd, RawFd};
 
         const O_DIRECT: i32 = 0o0_040_000;
 
@@ -70,12 +95,28 @@ cfg_if! {
                 .open(path)
         }
 
+        pub fn create_using_direct_io<P: AsRef<Path>>(path: P) -> io::Result<File> {
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .custom_flags(O_DIRECT)
+                .open(path)
+        }
+
+        pub fn create<P: AsRef<Path>>(path: P) -> io::Result<File> {
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+        }
+
     } else {
         use std::os::windows::fs::OpenOptionsExt;
 
         const FILE_FLAG_NO_BUFFERING: u32 = 0x2000_0000;
         const FILE_FLAG_SEQUENTIAL_SCAN: u32 = 0x0800_0000;
         const FILE_FLAG_RANDOM_ACCESS: u32 = 0x1000_0000;
+        const FILE_FLAG_WRITE_THROUGH: u32 = 0x8000_0000;
 
         pub fn open_using_direct_io<P: AsRef<Path>>(path: P) -> io::Result<File> {
             OpenOptions::new()
@@ -90,11 +131,45 @@ cfg_if! {
                 .custom_flags(FILE_FLAG_SEQUENTIAL_SCAN | FILE_FLAG_RANDOM_ACCESS)
                 .open(path)
         }
+
+        pub fn create_using_direct_io<P: AsRef<Path>>(path: P) -> io::Result<File> {
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .custom_flags(FILE_FLAG_NO_BUFFERING | FILE_FLAG_WRITE_THROUGH)
+                .open(path)
+        }
+
+        pub fn create<P: AsRef<Path>>(path: P) -> io::Result<File> {
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+        }
     }
 }
 
 impl Plot {
-    pub fn new(path: &PathBuf, mut use_direct_io: bool, dummy: bool) -> Result<Plot, Box<dyn Error>> {
+    pub fn new(path: &PathBuf, use_direct_io: bool, dummy: bool) -> Result<Plot, Box<dyn Error>> {
+        Plot::open(path, use_direct_io, dummy, None)
+    }
+
+    // skips the sector size detection, which needs to shell out on most platforms
+    pub fn with_sector_size(
+        path: &PathBuf,
+        sector_size: u64,
+        use_direct_io: bool,
+        dummy: bool,
+    ) -> Result<Plot, Box<dyn Error>> {
+        Plot::open(path, use_direct_io, dummy, Some(sector_size))
+    }
+
+    fn open(
+        path: &PathBuf,
+        mut use_direct_io: bool,
+        dummy: bool,
+        sector_size: Option<u64>,
+    ) -> Result<Plot, Box<dyn Error>> {
         if !path.is_file() {
             return Err(From::from(format!(
                 "{} is not a file",
@@ -103,14 +178,12 @@ impl Plot {
         }
 
         let plot_file = path.file_name().unwrap().to_str().unwrap();
-        let parts: Vec<&str> = plot_file.split('_').collect();
-        if parts.len() != 3 {
-            return Err(From::from("plot file has wrong format"));
-        }
+        let (account_id, start_nonce, nonces, stagger) = parse_file_name(path)?;
 
-        let account_id = parts[0].parse::<u64>()?;
-        let start_nonce = parts[1].parse::<u64>()?;
-        let nonces = parts[2].parse::<u64>()?;
+        // the scoop halves of PoC1 plots are swapped on read, which needs buffered io
+        if stagger.is_some() {
+            use_direct_io = false;
+        }
 
         let size = fs::metadata(path)?.len();
         let exp_size = nonces * NONCE_SIZE;
@@ -128,7 +201,10 @@ impl Plot {
         };
 
         let plot_file_name = plot_file.to_string();
-        let sector_size = get_sector_size(&path.to_str().unwrap().to_owned());
+        let sector_size = match sector_size {
+            Some(sector_size) => sector_size,
+            None => get_sector_size(path.to_str().unwrap())?,
+        };
         if use_direct_io && sector_size / 64 > nonces {
             warn!(
                 "not enough nonces for using direct io: plot={}",
@@ -143,9 +219,12 @@ impl Plot {
                 account_id,
                 start_nonce,
                 nonces,
+                stagger,
                 name: plot_file_name,
             },
             fh,
+            fh_buffered: None,
+            mirror: Vec::new(),
             path: file_path,
             read_offset: 0,
             use_direct_io,
@@ -154,63 +233,181 @@ impl Plot {
         })
     }
 
+    pub fn sector_size(&self) -> u64 {
+        self.sector_size
+    }
+
+    #[cfg(all(feature = "io_uring", target_os = "linux"))]
+    pub fn is_dummy(&self) -> bool {
+        self.dummy
+    }
+
+    // continues reading with a read that was planned but not done
+    #[cfg(all(feature = "io_uring", target_os = "linux"))]
+    pub fn replan(&mut self, plan: &ReadPlan) {
+        self.read_offset = (plan.start_nonce - self.meta.start_nonce) * SCOOP_SIZE;
+    }
+
+    // raw handles for (direct, buffered) reads as planned by plan_read
+    #[cfg(all(feature = "io_uring", target_os = "linux"))]
+    pub fn raw_fds(&self) -> (RawFd, RawFd) {
+        let buffered = self.fh_buffered.as_ref().unwrap_or(&self.fh);
+        (self.fh.as_raw_fd(), buffered.as_raw_fd())
+    }
+
     pub fn prepare(&mut self, scoop: u32) -> io::Result<u64> {
         self.read_offset = 0;
         let nonces = self.meta.nonces;
-        let mut seek_addr = u64::from(scoop) * nonces as u64 * SCOOP_SIZE;
+        let seek_addr = u64::from(scoop) * nonces * SCOOP_SIZE;
 
         // reopening file handles
         if !self.use_direct_io {
             self.fh = open(&self.path)?;
         } else {
             self.fh = open_using_direct_io(&self.path)?;
+            self.fh_buffered = Some(open(&self.path)?);
         };
 
-        if self.use_direct_io {
-            self.read_offset = self.round_seek_addr(&mut seek_addr);
-        }
+        self.fh.seek(SeekFrom::Start(seek_addr))
+    }
 
+    // positions the next read at the given nonce (relative to the start nonce), buffered io only
+    pub fn prepare_nonce(&mut self, scoop: u32, nonce: u64) -> io::Result<u64> {
+        self.read_offset = nonce * SCOOP_SIZE;
+        let seek_addr = u64::from(scoop) * self.meta.nonces * SCOOP_SIZE + self.read_offset;
         self.fh.seek(SeekFrom::Start(seek_addr))
     }
 
     pub fn read(&mut self, bs: &mut Vec<u8>, scoop: u32) -> Result<(usize, u64, bool), io::Error> {
+        self.read_interruptible(bs, scoop, &|| false)
+    }
+
+    /// Like `read`, but gives up with `ErrorKind::Interrupted` as soon as `interrupted` returns
+    /// true, which is checked before every chunk of `READ_CHUNK_SIZE` bytes.
+    pub fn read_interruptible(
+        &mut self,
+        bs: &mut Vec<u8>,
+        scoop: u32,
+        interrupted: &dyn Fn() -> bool,
+    ) -> Result<(usize, u64, bool), io::Error> {
+        if let Some(stagger) = self.meta.stagger {
+            return self.read_poc1(bs, scoop, stagger, interrupted);
+        }
+
+        let plan = self.plan_read(bs.capacity() as u64, scoop);
+        let bytes_to_read = plan.len();
+
+        if !self.dummy {
+            if plan.direct_bytes > 0 {
+                // chunks have to stay sector aligned for direct io
+                let chunk_size =
+                    (READ_CHUNK_SIZE + self.sector_size - 1) / self.sector_size * self.sector_size;
+                self.fh.seek(SeekFrom::Start(plan.addr))?;
+                read_chunked(
+                    &mut self.fh,
+                    &mut bs[0..plan.direct_bytes as usize],
+                    chunk_size as usize,
+                    interrupted,
+                )?;
+            }
+            if plan.buffered_bytes > 0 {
+                let fh = match self.fh_buffered {
+                    Some(ref mut fh) => fh,
+                    None => &mut self.fh,
+                };
+                fh.seek(SeekFrom::Start(plan.addr + plan.direct_bytes))?;
+                fh.read_exact(&mut bs[plan.direct_bytes as usize..bytes_to_read])?;
+            }
+        }
+
+        Ok((bytes_to_read, plan.start_nonce, plan.finished))
+    }
+
+    /// Determines where the next chunk of a PoC2 scoop is read from without reading it and
+    /// advances the read position past it.
+    pub fn plan_read(&mut self, buffer_cap: u64, scoop: u32) -> ReadPlan {
         let read_offset = self.read_offset;
-        let buffer_cap = bs.capacity();
         let start_nonce = self.meta.start_nonce + self.read_offset / 64;
-
-        let (bytes_to_read, finished) =
-            if read_offset as usize + buffer_cap >= (SCOOP_SIZE * self.meta.nonces) as usize {
-                let mut bytes_to_read =
-                    (SCOOP_SIZE * self.meta.nonces) as usize - self.read_offset as usize;
-                if self.use_direct_io {
-                    let r = bytes_to_read % self.sector_size as usize;
-                    if r != 0 {
-                        bytes_to_read -= r;
-                    }
+        let scoop_size = SCOOP_SIZE * self.meta.nonces;
+        let remaining = scoop_size - read_offset;
+        let seek_addr = read_offset + u64::from(scoop) * scoop_size;
+
+        // With direct io only sector aligned regions can be read. The unaligned head and tail
+        // of a scoop are read with a buffered handle, the head as a chunk of its own so that
+        // the following direct reads stay aligned in memory.
+        let (direct_bytes, buffered_bytes) = if !self.use_direct_io {
+            (min(buffer_cap, remaining), 0)
+        } else {
+            let head = (self.sector_size - seek_addr % self.sector_size) % self.sector_size;
+            if head > 0 {
+                (0, min(min(head, remaining), buffer_cap))
+            } else {
+                let aligned = min(buffer_cap, remaining) / self.sector_size * self.sector_size;
+                if remaining - aligned < self.sector_size && remaining <= buffer_cap {
+                    (aligned, remaining - aligned)
+                } else {
+                    (aligned, 0)
                 }
+            }
+        };
+        self.read_offset += direct_bytes + buffered_bytes;
+
+        ReadPlan {
+            addr: seek_addr,
+            direct_bytes,
+            buffered_bytes,
+            start_nonce,
+            finished: self.read_offset == scoop_size,
+        }
+    }
 
-                (bytes_to_read, true)
-            } else {
-                (buffer_cap as usize, false)
-            };
+    // Reads PoC1 scoops as PoC2 scoops: the second hash of scoop i is taken from scoop 4095 - i.
+    // A single read never crosses a stagger group.
+    fn read_poc1(
+        &mut self,
+        bs: &mut Vec<u8>,
+        scoop: u32,
+        stagger: u64,
+        interrupted: &dyn Fn() -> bool,
+    ) -> Result<(usize, u64, bool), io::Error> {
+        let nonce = self.read_offset / SCOOP_SIZE;
+        let start_nonce = self.meta.start_nonce + nonce;
+        let group_remaining = (stagger - nonce % stagger) * SCOOP_SIZE;
+        let buffer_cap = bs.capacity() as u64 / SCOOP_SIZE * SCOOP_SIZE;
+        let bytes_to_read = min(buffer_cap, group_remaining) as usize;
 
-        let offset = self.read_offset;
-        let nonces = self.meta.nonces;
-        let seek_addr =
-            SeekFrom::Start(offset as u64 + u64::from(scoop) * nonces as u64 * SCOOP_SIZE);
         if !self.dummy {
-            self.fh.seek(seek_addr)?;
-            self.fh.read_exact(&mut bs[0..bytes_to_read])?;
-            // interrupt avoider (not implemented)
-            // let read_chunk_size_in_nonces = 65536;
-            // for i in (0..bytes_to_read).step_by(read_chunk_size_in_nonces) {
-            //     self.fh.read_exact(
-            //         &mut bs[i..(i + min(read_chunk_size_in_nonces, bytes_to_read - i))],
-            //     )?;
-            // }
+            let mirror_scoop = SCOOPS_IN_NONCE as u32 - scoop - 1;
+            self.fh
+                .seek(SeekFrom::Start(poc1_addr(scoop, nonce, stagger)))?;
+            read_chunked(
+                &mut self.fh,
+                &mut bs[0..bytes_to_read],
+                READ_CHUNK_SIZE as usize,
+                interrupted,
+            )?;
+
+            self.mirror.resize(bytes_to_read, 0);
+            self.fh
+                .seek(SeekFrom::Start(poc1_addr(mirror_scoop, nonce, stagger)))?;
+            read_chunked(
+                &mut self.fh,
+                &mut self.mirror,
+                READ_CHUNK_SIZE as usize,
+                interrupted,
+            )?;
+
+            let hash_size = SHABAL256_HASH_SIZE as usize;
+            for (dst, src) in bs[0..bytes_to_read]
+                .chunks_mut(SCOOP_SIZE as usize)
+                .zip(self.mirror.chunks(SCOOP_SIZE as usize))
+            {
+                dst[hash_size..].copy_from_slice(&src[hash_size..]);
+            }
         }
         self.read_offset += bytes_to_read as u64;
 
+        let finished = self.read_offset == self.meta.nonces * SCOOP_SIZE;
         Ok((bytes_to_read, start_nonce, finished))
     }
 
@@ -237,3 +434,177 @@ impl Plot {
         }
     }
 }
+
+fn read_chunked(
+    fh: &mut File,
+    buf: &mut [u8],
+    chunk_size: usize,
+    interrupted: &dyn Fn() -> bool,
+) -> io::Result<()> {
+    for chunk in buf.chunks_mut(chunk_size) {
+        if interrupted() {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "read interrupted",
+            ));
+        }
+        fh.read_exact(chunk)?;
+    }
+    Ok(())
+}
+
+/// Account id, start nonce, nonces and, for PoC1 plots, the stagger.
+pub type FileName = (u64, u64, u64, Option<u64>);
+
+/// Parses the name of a plot file.
+pub fn parse_file_name(path: &Path) -> Result<FileName, Box<dyn Error>> {
+    let plot_file = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    let parts: Vec<&str> = plot_file.split('_').collect();
+    if parts.len() != 3 && parts.len() != 4 {
+        return Err(From::from("plot file has wrong format"));
+    }
+
+    let account_id = parts[0].parse::<u64>()?;
+    let start_nonce = parts[1].parse::<u64>()?;
+    let nonces = parts[2].parse::<u64>()?;
+    let stagger = match parts.get(3) {
+        Some(stagger) => {
+            let stagger = stagger.parse::<u64>()?;
+            if stagger == 0 || nonces % stagger != 0 {
+                return Err(From::from(format!(
+                    "nonces {} are not a multiple of stagger {}",
+                    nonces, stagger
+                )));
+            }
+            Some(stagger)
+        }
+        None => None,
+    };
+    Ok((account_id, start_nonce, nonces, stagger))
+}
+
+/// Offset of a scoop of a nonce (relative to the start nonce) in a PoC1 plot.
+pub fn poc1_addr(scoop: u32, nonce: u64, stagger: u64) -> u64 {
+    let group = nonce / stagger;
+    group * stagger * NONCE_SIZE + (u64::from(scoop) * stagger + nonce % stagger) * SCOOP_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miner::{Buffer, CpuBuffer};
+    use std::cell::Cell;
+    use std::env;
+
+    // every scoop of every nonce is tagged with its nonce and scoop number
+    fn write_tagged_plot(dir: &Path, start_nonce: u64, nonces: u64) -> PathBuf {
+        let mut data = vec![0u8; (nonces * NONCE_SIZE) as usize];
+        for scoop in 0..SCOOPS_IN_NONCE {
+            for n in 0..nonces {
+                let offset = ((scoop * nonces + n) * SCOOP_SIZE) as usize;
+                data[offset..offset + 8].copy_from_slice(&(start_nonce + n).to_le_bytes());
+                data[offset + 8..offset + 16].copy_from_slice(&scoop.to_le_bytes());
+            }
+        }
+        let path = dir.join(format!("1_{}_{}", start_nonce, nonces));
+        fs::write(&path, &data).unwrap();
+        path
+    }
+
+    fn read_scoop(plot: &mut Plot, scoop: u32, buffer_size: usize) -> Vec<u64> {
+        let mut buffer = CpuBuffer::new(buffer_size);
+        let bs = buffer.get_buffer();
+        let mut bs = bs.lock().unwrap();
+
+        let mut nonces = Vec::new();
+        plot.prepare(scoop).unwrap();
+        loop {
+            let (bytes_read, start_nonce, finished) = plot.read(&mut bs, scoop).unwrap();
+            assert!(bytes_read > 0);
+            assert_eq!(bytes_read % SCOOP_SIZE as usize, 0);
+            for (i, chunk) in bs[..bytes_read].chunks(SCOOP_SIZE as usize).enumerate() {
+                let mut tag = [0u8; 8];
+                tag.copy_from_slice(&chunk[0..8]);
+                let nonce = u64::from_le_bytes(tag);
+                tag.copy_from_slice(&chunk[8..16]);
+                assert_eq!(u64::from_le_bytes(tag), u64::from(scoop));
+                assert_eq!(nonce, start_nonce + i as u64);
+                nonces.push(nonce);
+            }
+            if finished {
+                break;
+            }
+        }
+        nonces
+    }
+
+    #[test]
+    fn test_read_odd_nonce_counts() {
+        let dir = env::temp_dir().join("scavenger_test_read");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        for &nonces in &[1, 3, 67, 129, 201] {
+            let path = write_tagged_plot(&dir, 1000, nonces);
+            for &use_direct_io in &[false, true] {
+                let mut plot = Plot::new(&path, use_direct_io, false).unwrap();
+                for &scoop in &[0, 1, 7, 2048, 4095] {
+                    for &buffer_size in &[4096, 8192, 65536] {
+                        let read = read_scoop(&mut plot, scoop, buffer_size);
+                        let expected: Vec<u64> = (1000..1000 + nonces).collect();
+                        assert_eq!(
+                            read, expected,
+                            "nonces={}, direct_io={}, scoop={}, buffer={}",
+                            nonces, use_direct_io, scoop, buffer_size
+                        );
+                    }
+                }
+            }
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_interruptible() {
+        let dir = env::temp_dir().join("scavenger_test_read_interruptible");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        // a sparse plot with scoops of two chunks
+        let nonces = 2 * READ_CHUNK_SIZE / SCOOP_SIZE;
+        let path = dir.join(format!("1_0_{}", nonces));
+        File::create(&path)
+            .unwrap()
+            .set_len(nonces * NONCE_SIZE)
+            .unwrap();
+
+        let mut plot = Plot::new(&path, false, false).unwrap();
+        let mut buffer = CpuBuffer::new(4 * READ_CHUNK_SIZE as usize);
+        let bs = buffer.get_buffer();
+        let mut bs = bs.lock().unwrap();
+
+        // interrupting after the first chunk stops the read before the second one
+        let checks = Cell::new(0);
+        let interrupted = || {
+            checks.set(checks.get() + 1);
+            checks.get() > 1
+        };
+        plot.prepare(1).unwrap();
+        let e = plot
+            .read_interruptible(&mut bs, 1, &interrupted)
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::Interrupted);
+        assert_eq!(checks.get(), 2);
+
+        plot.prepare(1).unwrap();
+        let (bytes_read, _, finished) = plot.read(&mut bs, 1).unwrap();
+        assert_eq!(bytes_read as u64, 2 * READ_CHUNK_SIZE);
+        assert!(finished);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+use crate::utils::get_device_id;
+use filetime::FileTime;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Cached properties of a plot file that are expensive to determine.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub size: u64,
+    pub mtime: i64,
+    // device number the drive id and sector size were determined for, device numbers change
+    // across reboots and remounts and copied plots keep their size and mtime
+    #[serde(default)]
+    pub dev: Option<u64>,
+    pub drive_id: String,
+    pub sector_size: u64,
+}
+
+/// Plot metadata persisted between runs, keyed by path. An entry is only trusted as long as
+/// size, mtime and device of its file are unchanged.
+pub struct PlotIndex {
+    path: Option<PathBuf>,
+    entries: HashMap<PathBuf, IndexEntry>,
+    dirty: bool,
+}
+
+impl PlotIndex {
+    /// Loads the index from `path`, a missing or unreadable index starts out empty. Without a
+    /// path nothing is persisted.
+    pub fn load(path: Option<&Path>) -> PlotIndex {
+        let entries = match path {
+            Some(path) => match fs::read(path) {
+                Ok(data) => match serde_json::from_slice(&data) {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        warn!(
+                            "plot index {} is corrupted, rebuilding: {}",
+                            path.to_str().unwrap(),
+                            e
+                        );
+                        HashMap::new()
+                    }
+                },
+                Err(_) => HashMap::new(),
+            },
+            None => HashMap::new(),
+        };
+        PlotIndex {
+            path: path.map(Path::to_path_buf),
+            entries,
+            dirty: false,
+        }
+    }
+
+    /// Returns the entry of `file` if it is still valid for the given metadata.
+    pub fn get(&self, file: &Path, metadata: &fs::Metadata) -> Option<&IndexEntry> {
+        self.entries.get(file).filter(|entry| {
+            entry.size == metadata.len()
+                && entry.mtime == mtime(metadata)
+                && entry.dev == dev(metadata)
+        })
+    }
+
+    /// Adds or replaces the entry of `file`, the drive id is determined here.
+    pub fn insert(
+        &mut self,
+        file: &Path,
+        metadata: &fs::Metadata,
+        sector_size: u64,
+    ) -> io::Result<&IndexEntry> {
+        let entry = IndexEntry {
+            size: metadata.len(),
+            mtime: mtime(metadata),
+            dev: dev(metadata),
+            drive_id: get_device_id(file.to_str().unwrap())?,
+            sector_size,
+        };
+        self.dirty = true;
+        self.entries.insert(file.to_path_buf(), entry);
+        Ok(&self.entries[file])
+    }
+
+    /// Drops the entries of all files that haven't been seen.
+    pub fn retain(&mut self, seen: &HashSet<PathBuf>) {
+        let len = self.entries.len();
+        self.entries.retain(|path, _| seen.contains(path));
+        self.dirty |= self.entries.len() != len;
+    }
+
+    /// Writes the index if anything changed since it was loaded or saved.
+    pub fn save(&mut self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) if self.dirty => path,
+            _ => return Ok(()),
+        };
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(&self.entries)?)?;
+        fs::rename(&tmp_path, path)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+pub fn mtime(metadata: &fs::Metadata) -> i64 {
+    FileTime::from_last_modification_time(metadata).unix_seconds()
+}
+
+// the device number if the platform has one, volume paths on windows are stable
+#[cfg(unix)]
+fn dev(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn dev(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_plot_index() {
+        let dir = env::temp_dir().join("scavenger_test_plot_index");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let index_path = dir.join("plot_index.json");
+        let plot = dir.join("1_0_1");
+        fs::write(&plot, vec![0u8; 64]).unwrap();
+
+        let mut index = PlotIndex::load(Some(&index_path));
+        let metadata = fs::metadata(&plot).unwrap();
+        assert!(index.get(&plot, &metadata).is_none());
+        let entry = index.insert(&plot, &metadata, 4096).unwrap().clone();
+        index.save().unwrap();
+
+        let mut index = PlotIndex::load(Some(&index_path));
+        assert_eq!(index.get(&plot, &metadata), Some(&entry));
+
+        // a changed file invalidates its entry
+        fs::write(&plot, vec![0u8; 128]).unwrap();
+        let metadata = fs::metadata(&plot).unwrap();
+        assert!(index.get(&plot, &metadata).is_none());
+
+        // so does a copy on another device that kept size and mtime
+        #[cfg(unix)]
+        {
+            let mut copied = index.insert(&plot, &metadata, 4096).unwrap().clone();
+            assert!(index.get(&plot, &metadata).is_some());
+            copied.dev = copied.dev.map(|dev| dev + 1);
+            index.entries.insert(plot.clone(), copied);
+            assert!(index.get(&plot, &metadata).is_none());
+        }
+
+        index.retain(&HashSet::new());
+        index.save().unwrap();
+        assert!(PlotIndex::load(Some(&index_path)).entries.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
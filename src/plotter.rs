@@ -0,0 +1,533 @@
+use crate::miner::{Buffer, CpuBuffer};
+use crate::plot::{
+    create, create_using_direct_io, NONCE_SIZE, SCOOPS_IN_NONCE, SCOOP_SIZE, SHABAL256_HASH_SIZE,
+};
+use crate::shabal256::shabal256_hash_fast;
+use crate::utils::{get_sector_size, new_thread_pool};
+#[cfg(any(feature = "simd", feature = "neon"))]
+use libc::c_void;
+use pbr::{ProgressBar, Units};
+use std::cmp::min;
+use std::error::Error;
+use std::fs;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::thread;
+
+const HASH_CAP: usize = 4096;
+const SEED_SIZE: usize = 16;
+
+cfg_if! {
+    if #[cfg(feature = "simd")] {
+        extern "C" {
+            pub fn noncegen_avx512f(
+                cache: *mut c_void,
+                cache_size: usize,
+                chunk_offset: usize,
+                numeric_id: u64,
+                local_startnonce: u64,
+                local_nonces: u64,
+            ) -> ();
+
+            pub fn noncegen_avx2(
+                cache: *mut c_void,
+                cache_size: usize,
+                chunk_offset: usize,
+                numeric_id: u64,
+                local_startnonce: u64,
+                local_nonces: u64,
+            ) -> ();
+
+            pub fn noncegen_avx(
+                cache: *mut c_void,
+                cache_size: usize,
+                chunk_offset: usize,
+                numeric_id: u64,
+                local_startnonce: u64,
+                local_nonces: u64,
+            ) -> ();
+
+            pub fn noncegen_sse2(
+                cache: *mut c_void,
+                cache_size: usize,
+                chunk_offset: usize,
+                numeric_id: u64,
+                local_startnonce: u64,
+                local_nonces: u64,
+            ) -> ();
+        }
+
+        // returns the number of nonces generated, the rest is left to the rust implementation
+        unsafe fn noncegen_simd(
+            cache: *mut u8,
+            cache_size: usize,
+            chunk_offset: usize,
+            account_id: u64,
+            start_nonce: u64,
+            nonces: u64,
+        ) -> u64 {
+            let cache = cache as *mut c_void;
+            if is_x86_feature_detected!("avx512f") {
+                let nonces = nonces / 16 * 16;
+                noncegen_avx512f(cache, cache_size, chunk_offset, account_id, start_nonce, nonces);
+                nonces
+            } else if is_x86_feature_detected!("avx2") {
+                let nonces = nonces / 8 * 8;
+                noncegen_avx2(cache, cache_size, chunk_offset, account_id, start_nonce, nonces);
+                nonces
+            } else if is_x86_feature_detected!("avx") {
+                let nonces = nonces / 4 * 4;
+                noncegen_avx(cache, cache_size, chunk_offset, account_id, start_nonce, nonces);
+                nonces
+            } else if is_x86_feature_detected!("sse2") {
+                let nonces = nonces / 4 * 4;
+                noncegen_sse2(cache, cache_size, chunk_offset, account_id, start_nonce, nonces);
+                nonces
+            } else {
+                0
+            }
+        }
+    } else if #[cfg(feature = "neon")] {
+        extern "C" {
+            pub fn noncegen_neon(
+                cache: *mut c_void,
+                cache_size: usize,
+                chunk_offset: usize,
+                numeric_id: u64,
+                local_startnonce: u64,
+                local_nonces: u64,
+            ) -> ();
+        }
+
+        // returns the number of nonces generated, the rest is left to the rust implementation
+        unsafe fn noncegen_simd(
+            cache: *mut u8,
+            cache_size: usize,
+            chunk_offset: usize,
+            account_id: u64,
+            start_nonce: u64,
+            nonces: u64,
+        ) -> u64 {
+            #[cfg(target_arch = "arm")]
+            let neon = is_arm_feature_detected!("neon");
+            #[cfg(target_arch = "aarch64")]
+            let neon = true;
+
+            if neon {
+                let nonces = nonces / 4 * 4;
+                noncegen_neon(
+                    cache as *mut c_void,
+                    cache_size,
+                    chunk_offset,
+                    account_id,
+                    start_nonce,
+                    nonces,
+                );
+                nonces
+            } else {
+                0
+            }
+        }
+    } else {
+        unsafe fn noncegen_simd(
+            _cache: *mut u8,
+            _cache_size: usize,
+            _chunk_offset: usize,
+            _account_id: u64,
+            _start_nonce: u64,
+            _nonces: u64,
+        ) -> u64 {
+            0
+        }
+    }
+}
+
+// shabal256 of an arbitrary message whose length is a multiple of 4
+fn shabal256(data: &[u8]) -> [u8; 32] {
+    let full_blocks = data.len() / 64 * 64;
+    let tail = &data[full_blocks..];
+    let mut term_bytes = [0u8; 64];
+    term_bytes[..tail.len()].clone_from_slice(tail);
+    term_bytes[tail.len()] = 0x80;
+
+    let mut term = [0u32; 16];
+    for (word, bytes) in term.iter_mut().zip(term_bytes.chunks(4)) {
+        *word = u32::from(bytes[0])
+            | u32::from(bytes[1]) << 8
+            | u32::from(bytes[2]) << 16
+            | u32::from(bytes[3]) << 24;
+    }
+    shabal256_hash_fast(&data[..full_blocks], &term)
+}
+
+/// Generates a single nonce in PoC1 order, i.e. scoop by scoop.
+pub fn generate_nonce(account_id: u64, nonce: u64) -> Vec<u8> {
+    let nonce_size = NONCE_SIZE as usize;
+    let hash_size = SHABAL256_HASH_SIZE as usize;
+
+    let mut gendata = vec![0u8; nonce_size + SEED_SIZE];
+    gendata[nonce_size..nonce_size + 8].clone_from_slice(&account_id.to_be_bytes());
+    gendata[nonce_size + 8..].clone_from_slice(&nonce.to_be_bytes());
+
+    for i in (hash_size..=nonce_size).rev().step_by(hash_size) {
+        let len = min(nonce_size + SEED_SIZE - i, HASH_CAP);
+        let hash = shabal256(&gendata[i..i + len]);
+        gendata[i - hash_size..i].clone_from_slice(&hash);
+    }
+
+    let final_hash = shabal256(&gendata);
+    gendata.truncate(nonce_size);
+    for (i, byte) in gendata.iter_mut().enumerate() {
+        *byte ^= final_hash[i % hash_size];
+    }
+    gendata
+}
+
+unsafe fn noncegen_rust(
+    cache: *mut u8,
+    cache_size: usize,
+    chunk_offset: usize,
+    account_id: u64,
+    start_nonce: u64,
+    nonces: u64,
+) {
+    let scoop_size = SCOOP_SIZE as usize;
+    let hash_size = SHABAL256_HASH_SIZE as usize;
+    let scoops = SCOOPS_IN_NONCE as usize;
+
+    for n in 0..nonces as usize {
+        let nonce = generate_nonce(account_id, start_nonce + n as u64);
+        for scoop in 0..scoops {
+            let dst = cache.add((scoop * cache_size + chunk_offset + n) * scoop_size);
+            ptr::copy_nonoverlapping(nonce.as_ptr().add(scoop * scoop_size), dst, hash_size);
+            // PoC2: the second hash of scoop i is taken from scoop 4095 - i
+            ptr::copy_nonoverlapping(
+                nonce
+                    .as_ptr()
+                    .add((scoops - scoop - 1) * scoop_size + hash_size),
+                dst.add(hash_size),
+                hash_size,
+            );
+        }
+    }
+}
+
+/// Generates `nonces` PoC2 nonces into a scoop-major cache that holds `cache_size` nonces per
+/// scoop. The nonces are written to the slots starting at `chunk_offset`.
+pub unsafe fn noncegen(
+    cache: *mut u8,
+    cache_size: usize,
+    chunk_offset: usize,
+    account_id: u64,
+    start_nonce: u64,
+    nonces: u64,
+) {
+    let simd_nonces = noncegen_simd(
+        cache,
+        cache_size,
+        chunk_offset,
+        account_id,
+        start_nonce,
+        nonces,
+    );
+    noncegen_rust(
+        cache,
+        cache_size,
+        chunk_offset + simd_nonces as usize,
+        account_id,
+        start_nonce + simd_nonces,
+        nonces - simd_nonces,
+    );
+}
+
+pub struct Plotter {
+    account_id: u64,
+    start_nonce: u64,
+    nonces: u64,
+    path: PathBuf,
+    nonces_per_cache: u64,
+    cpu_threads: usize,
+    pool: rayon::ThreadPool,
+    use_direct_io: bool,
+    show_progress: bool,
+}
+
+impl Plotter {
+    pub fn new(
+        account_id: u64,
+        start_nonce: u64,
+        mut nonces: u64,
+        output_dir: &Path,
+        nonces_per_cache: u64,
+        cpu_threads: usize,
+        thread_pinning: bool,
+        use_direct_io: bool,
+        show_progress: bool,
+    ) -> Result<Plotter, Box<dyn Error>> {
+        if !output_dir.is_dir() {
+            return Err(From::from(format!(
+                "{} is not a directory",
+                output_dir.to_str().unwrap()
+            )));
+        }
+
+        // direct io needs every write to be a multiple of the sector size
+        let mut nonces_per_cache = nonces_per_cache;
+        if use_direct_io {
+            let sector_size = get_sector_size(output_dir.to_str().unwrap())?;
+            let nonces_per_sector = sector_size / SCOOP_SIZE;
+            if nonces % nonces_per_sector != 0 {
+                nonces -= nonces % nonces_per_sector;
+                warn!(
+                    "nonces have to be a multiple of {} when using direct io, using {} nonces",
+                    nonces_per_sector, nonces
+                );
+            }
+            nonces_per_cache -= nonces_per_cache % nonces_per_sector;
+            if nonces_per_cache == 0 {
+                nonces_per_cache = nonces_per_sector;
+            }
+        }
+        if nonces == 0 {
+            return Err(From::from("nothing to plot: nonces=0"));
+        }
+
+        let path = output_dir.join(format!("{}_{}_{}", account_id, start_nonce, nonces));
+        if path.exists() {
+            return Err(From::from(format!(
+                "{} already exists",
+                path.to_str().unwrap()
+            )));
+        }
+
+        Ok(Plotter {
+            account_id,
+            start_nonce,
+            nonces,
+            path,
+            nonces_per_cache: min(nonces_per_cache, nonces),
+            cpu_threads,
+            pool: new_thread_pool(cpu_threads, thread_pinning),
+            use_direct_io,
+            show_progress,
+        })
+    }
+
+    /// Plots into a temporary file which is renamed once all nonces are written, so that a
+    /// miner never picks up an incomplete plot.
+    pub fn run(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let tmp_path = self.path.with_extension("tmp");
+        let mut fh = if self.use_direct_io {
+            create_using_direct_io(&tmp_path)?
+        } else {
+            create(&tmp_path)?
+        };
+        fh.set_len(self.nonces * NONCE_SIZE)?;
+
+        info!(
+            "plotting: file={}, nonces={}, nonces_per_cache={}, direct_io={}",
+            self.path.to_str().unwrap(),
+            self.nonces,
+            self.nonces_per_cache,
+            self.use_direct_io
+        );
+
+        // two caches: one is hashed while the other one is written
+        let buffer_size = (self.nonces_per_cache * NONCE_SIZE) as usize;
+        let (tx_empty_buffers, rx_empty_buffers) = crossbeam_channel::bounded(2);
+        let (tx_full_buffers, rx_full_buffers) =
+            crossbeam_channel::bounded::<(CpuBuffer, u64, u64)>(2);
+        for _ in 0..2 {
+            tx_empty_buffers.send(CpuBuffer::new(buffer_size)).unwrap();
+        }
+
+        let nonces = self.nonces;
+        let nonces_per_cache = self.nonces_per_cache;
+        let mut pb = if self.show_progress {
+            let mut pb = ProgressBar::new(nonces * NONCE_SIZE);
+            pb.format("│██░│");
+            pb.set_width(Some(80));
+            pb.set_units(Units::Bytes);
+            pb.message("Plotting: ");
+            Some(pb)
+        } else {
+            None
+        };
+
+        let writer = thread::spawn(move || -> Result<(), String> {
+            for (mut buffer, chunk_offset, chunk_nonces) in rx_full_buffers {
+                let bs = buffer.get_buffer_for_writing();
+                let bs = bs.lock().unwrap();
+                for scoop in 0..SCOOPS_IN_NONCE {
+                    let src = (scoop * nonces_per_cache * SCOOP_SIZE) as usize;
+                    let len = (chunk_nonces * SCOOP_SIZE) as usize;
+                    let seek_addr = (scoop * nonces + chunk_offset) * SCOOP_SIZE;
+                    fh.seek(SeekFrom::Start(seek_addr))
+                        .and_then(|_| fh.write_all(&bs[src..src + len]))
+                        .map_err(|e| e.to_string())?;
+                }
+                drop(bs);
+                if let Some(pb) = &mut pb {
+                    pb.add(chunk_nonces * NONCE_SIZE);
+                }
+                tx_empty_buffers.send(buffer).unwrap();
+            }
+            fh.sync_all().map_err(|e| e.to_string())?;
+            if let Some(pb) = &mut pb {
+                pb.finish_print("");
+            }
+            Ok(())
+        });
+
+        for chunk_offset in (0..self.nonces).step_by(self.nonces_per_cache as usize) {
+            let chunk_nonces = min(self.nonces_per_cache, self.nonces - chunk_offset);
+            let mut buffer = match rx_empty_buffers.recv() {
+                Ok(buffer) => buffer,
+                // writer failed, its error is reported below
+                Err(_) => break,
+            };
+            {
+                let bs = buffer.get_buffer_for_writing();
+                let mut bs = bs.lock().unwrap();
+                self.hash_chunk(&mut bs, chunk_offset, chunk_nonces);
+            }
+            if tx_full_buffers
+                .send((buffer, chunk_offset, chunk_nonces))
+                .is_err()
+            {
+                break;
+            }
+        }
+        drop(tx_full_buffers);
+
+        writer.join().unwrap()?;
+        fs::rename(&tmp_path, &self.path)?;
+        info!("plot finished: file={}", self.path.to_str().unwrap());
+        Ok(self.path.clone())
+    }
+
+    fn hash_chunk(&self, bs: &mut [u8], chunk_offset: u64, chunk_nonces: u64) {
+        // split into multiples of the widest simd lane count
+        let cpu_threads = self.cpu_threads as u64;
+        let task_nonces = ((chunk_nonces + cpu_threads - 1) / cpu_threads + 15) / 16 * 16;
+        let cache = bs.as_mut_ptr() as usize;
+        let cache_size = self.nonces_per_cache as usize;
+        let account_id = self.account_id;
+        let start_nonce = self.start_nonce + chunk_offset;
+        self.pool.scope(|s| {
+            for offset in (0..chunk_nonces).step_by(task_nonces as usize) {
+                let nonces = min(task_nonces, chunk_nonces - offset);
+                s.spawn(move |_| unsafe {
+                    // every task writes to its own nonce slots only
+                    noncegen(
+                        cache as *mut u8,
+                        cache_size,
+                        offset as usize,
+                        account_id,
+                        start_nonce + offset,
+                        nonces,
+                    );
+                });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_plot() {
+        let dir = env::temp_dir().join("scavenger_test_plot");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        // plot the last three nonces of the test plot using two caches
+        let plotter =
+            Plotter::new(10282355196851764065, 5, 3, &dir, 2, 2, false, false, false).unwrap();
+        let path = plotter.run().unwrap();
+        assert_eq!(path, dir.join("10282355196851764065_5_3"));
+
+        let plot = fs::read(&path).unwrap();
+        let expected = fs::read("test_data/10282355196851764065_0_8").unwrap();
+        let scoop_size = SCOOP_SIZE as usize;
+        for scoop in 0..SCOOPS_IN_NONCE as usize {
+            let plotted = &plot[scoop * 3 * scoop_size..(scoop + 1) * 3 * scoop_size];
+            let mut expected =
+                &expected[(scoop * 8 + 5) * scoop_size..(scoop + 1) * 8 * scoop_size];
+            // the test plot has the last 8 bytes zeroed out
+            if scoop == SCOOPS_IN_NONCE as usize - 1 {
+                expected = &expected[..expected.len() - 8];
+            }
+            assert_eq!(
+                &plotted[..expected.len()],
+                expected,
+                "scoop {} differs",
+                scoop
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "simd")]
+    fn test_simd_noncegen() {
+        extern "C" {
+            pub fn init_shabal_avx512f() -> ();
+            pub fn init_shabal_avx2() -> ();
+            pub fn init_shabal_avx() -> ();
+            pub fn init_shabal_sse2() -> ();
+        }
+
+        // 16 nonces for the widest lanes, the first 8 of them are in the test plot
+        let nonces = 16;
+        let expected = fs::read("test_data/10282355196851764065_0_8").unwrap();
+        let check = |data: &[u8], ext: &str| {
+            let scoop_size = SCOOP_SIZE as usize;
+            for scoop in 0..SCOOPS_IN_NONCE as usize {
+                let plotted = &data[scoop * nonces * scoop_size..];
+                let mut expected = &expected[scoop * 8 * scoop_size..(scoop + 1) * 8 * scoop_size];
+                // the test plot has the last 8 bytes zeroed out
+                if scoop == SCOOPS_IN_NONCE as usize - 1 {
+                    expected = &expected[..expected.len() - 8];
+                }
+                assert_eq!(
+                    &plotted[..expected.len()],
+                    expected,
+                    "{}: scoop {} differs",
+                    ext,
+                    scoop
+                );
+            }
+        };
+
+        let mut data = vec![0u8; nonces * NONCE_SIZE as usize];
+        let cache = data.as_mut_ptr() as *mut c_void;
+        let account_id = 10282355196851764065;
+        unsafe {
+            if is_x86_feature_detected!("avx512f") {
+                init_shabal_avx512f();
+                noncegen_avx512f(cache, nonces, 0, account_id, 0, nonces as u64);
+                check(&data, "avx512f");
+            }
+            if is_x86_feature_detected!("avx2") {
+                init_shabal_avx2();
+                noncegen_avx2(cache, nonces, 0, account_id, 0, nonces as u64);
+                check(&data, "avx2");
+            }
+            if is_x86_feature_detected!("avx") {
+                init_shabal_avx();
+                noncegen_avx(cache, nonces, 0, account_id, 0, nonces as u64);
+                check(&data, "avx");
+            }
+            if is_x86_feature_detected!("sse2") {
+                init_shabal_sse2();
+                noncegen_sse2(cache, nonces, 0, account_id, 0, nonces as u64);
+                check(&data, "sse2");
+            }
+        }
+    }
+}
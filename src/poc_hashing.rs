@@ -1,3 +1,5 @@
+use crate::plot::SCOOPS_IN_NONCE;
+use crate::plotter::generate_nonce;
 use crate::shabal256::{shabal256_deadline_fast, shabal256_hash_fast};
 use hex;
 use std::mem::transmute;
@@ -18,12 +20,27 @@ pub fn calculate_scoop(height: u64, gensig: &[u8; 32]) -> u32 {
     data[..32].clone_from_slice(gensig);
     data[32..40].clone_from_slice(&height_bytes);
     data[40] = 0x80;
-    let data = unsafe { std::mem::transmute::<&[u8; 64], &[u32; 16]>(&data) };
+    let mut term = [0u32; 16];
+    for (word, bytes) in term.iter_mut().zip(data.chunks_exact(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
 
-    let new_gensig = &shabal256_hash_fast(&[], &data);
+    let new_gensig = &shabal256_hash_fast(&[], &term);
     (u32::from(new_gensig[30] & 0x0F) << 8) | u32::from(new_gensig[31])
 }
 
+/// Recomputes the unadjusted deadline of a nonce, e.g. to check deadlines claimed by other miners.
+pub fn calculate_deadline(account_id: u64, nonce: u64, scoop: u32, gensig: &[u8; 32]) -> u64 {
+    let nonce = generate_nonce(account_id, nonce);
+    let scoop = scoop as usize;
+    // PoC2: the second hash of scoop i is taken from scoop 4095 - i
+    let mirror = SCOOPS_IN_NONCE as usize - scoop - 1;
+    let mut data = [0u8; SCOOP_SIZE];
+    data[..32].copy_from_slice(&nonce[scoop * SCOOP_SIZE..scoop * SCOOP_SIZE + 32]);
+    data[32..].copy_from_slice(&nonce[mirror * SCOOP_SIZE + 32..(mirror + 1) * SCOOP_SIZE]);
+    shabal256_deadline_fast(&data, gensig)
+}
+
 pub fn find_best_deadline_rust(
     data: &[u8],
     number_of_nonces: u64,
@@ -41,3 +58,23 @@ pub fn find_best_deadline_rust(
     }
     (best_deadline, best_offset as u64)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plotter::noncegen;
+
+    #[test]
+    fn test_calculate_deadline() {
+        let gensig =
+            decode_gensig("9821beb3b34d9a3b30127c05f8d1e9006f8a02f565a3572145134bbe34d37a76");
+        let mut cache = vec![0u8; SCOOPS_IN_NONCE as usize * SCOOP_SIZE];
+        unsafe { noncegen(cache.as_mut_ptr(), 1, 0, 7, 100, 1) };
+        // the second half of a PoC2 scoop comes from the mirror scoop
+        for &scoop in &[0, 1234] {
+            let offset = scoop as usize * SCOOP_SIZE;
+            let plotted = find_best_deadline_rust(&cache[offset..offset + SCOOP_SIZE], 1, &gensig);
+            assert_eq!(plotted.0, calculate_deadline(7, 100, scoop, &gensig));
+        }
+    }
+}
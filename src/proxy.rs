@@ -0,0 +1,425 @@
+use crate::account::Account;
+use crate::com::api::MiningInfoResponse as MiningInfo;
+use crate::poc_hashing;
+use crate::requests::RequestHandler;
+use futures::future;
+use futures::{Future, Stream};
+use hyper::header::HeaderMap;
+use hyper::service::service_fn;
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::runtime::TaskExecutor;
+use url::form_urlencoded;
+
+// downstream miners that haven't asked for mining info for this long don't count anymore
+const MINER_TIMEOUT: Duration = Duration::from_secs(600);
+
+struct Round {
+    // mining info as served to downstream miners
+    body: String,
+    height: u64,
+    block: u64,
+    base_target: u64,
+    gen_sig: [u8; 32],
+    scoop: u32,
+    account_id_to_best_deadline: HashMap<u64, u64>,
+}
+
+struct DownstreamMiner {
+    capacity_gb: usize,
+    last_seen: Instant,
+}
+
+/// Serves the mining info of the upstream to miners on the LAN and forwards the best deadline
+/// of each account they submit.
+pub struct Proxy {
+    request_handler: RequestHandler,
+    round: Mutex<Option<Round>>,
+    miners: Mutex<HashMap<String, DownstreamMiner>>,
+    local_size_gb: AtomicUsize,
+}
+
+impl Proxy {
+    pub fn new(request_handler: RequestHandler, local_size_gb: usize) -> Proxy {
+        Proxy {
+            request_handler,
+            round: Mutex::new(None),
+            miners: Mutex::new(HashMap::new()),
+            local_size_gb: AtomicUsize::new(local_size_gb),
+        }
+    }
+
+    /// Starts serving requests of downstream miners on `addr`.
+    pub fn serve(proxy: Arc<Proxy>, addr: SocketAddr, executor: &TaskExecutor) {
+        let builder = match Server::try_bind(&addr) {
+            Ok(builder) => builder,
+            Err(e) => {
+                error!("proxy: can't listen on {}: {}", addr, e);
+                return;
+            }
+        };
+        info!("proxy: listening on {}", addr);
+        let server = builder
+            .serve(move || {
+                let proxy = proxy.clone();
+                service_fn(move |req| proxy.clone().handle(req))
+            })
+            .map_err(|e| error!("proxy: server failed: {}", e));
+        executor.spawn(server);
+    }
+
+    /// Replaces the mining info served, along with the block number the miner uses for it.
+    pub fn update_mining_info(&self, mining_info: &MiningInfo, block: u64, gen_sig: [u8; 32]) {
+        *self.round.lock().unwrap() = Some(Round {
+            body: serde_json::to_string(mining_info).unwrap(),
+            height: mining_info.height,
+            block,
+            base_target: mining_info.base_target,
+            gen_sig,
+            scoop: poc_hashing::calculate_scoop(mining_info.height, &gen_sig),
+            account_id_to_best_deadline: HashMap::new(),
+        });
+    }
+
+    /// Sets the capacity of the plots mined locally, downstream capacity is added on top.
+    pub fn set_local_size_gb(&self, local_size_gb: usize) {
+        self.local_size_gb.store(local_size_gb, Ordering::Relaxed);
+        self.report_capacity();
+    }
+
+    fn report_capacity(&self) {
+        let mut miners = self.miners.lock().unwrap();
+        miners.retain(|_, miner| miner.last_seen.elapsed() < MINER_TIMEOUT);
+        let downstream_size_gb: usize = miners.values().map(|miner| miner.capacity_gb).sum();
+        self.request_handler
+            .set_total_size_gb(self.local_size_gb.load(Ordering::Relaxed) + downstream_size_gb);
+    }
+
+    fn handle(
+        self: Arc<Self>,
+        req: Request<Body>,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = hyper::Error> + Send> {
+        if req.uri().path() != "/burst" {
+            return Box::new(future::ok(
+                Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::empty())
+                    .unwrap(),
+            ));
+        }
+
+        let (parts, body) = req.into_parts();
+        let is_post = parts.method == Method::POST;
+        Box::new(body.concat2().map(move |body| {
+            // parameters can come in the query as well as in a form body
+            let mut params: HashMap<String, String> = HashMap::new();
+            if let Some(query) = parts.uri.query() {
+                params.extend(form_urlencoded::parse(query.as_bytes()).into_owned());
+            }
+            if is_post {
+                params.extend(form_urlencoded::parse(&body).into_owned());
+            }
+
+            let (status, body) = self.respond(&params, &parts.headers);
+            Response::builder()
+                .status(status)
+                .header("Content-Type", "application/json")
+                .body(Body::from(body))
+                .unwrap()
+        }))
+    }
+
+    fn respond(
+        &self,
+        params: &HashMap<String, String>,
+        headers: &HeaderMap,
+    ) -> (StatusCode, String) {
+        self.register_miner(headers);
+        let res = match params.get("requestType").map(String::as_str) {
+            Some("getMiningInfo") => self.mining_info(),
+            Some("submitNonce") => self.submit_nonce(params, headers),
+            _ => Err((StatusCode::BAD_REQUEST, 1, "unknown requestType".to_owned())),
+        };
+        match res {
+            Ok(body) => (StatusCode::OK, body),
+            Err((status, code, message)) => (
+                status,
+                serde_json::json!({ "error": { "code": code, "message": message } }).to_string(),
+            ),
+        }
+    }
+
+    fn register_miner(&self, headers: &HeaderMap) {
+        let name = match headers.get("X-Minername").and_then(|v| v.to_str().ok()) {
+            Some(name) => name.to_owned(),
+            None => return,
+        };
+        let capacity_gb = headers
+            .get("X-Capacity")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+
+        let mut miners = self.miners.lock().unwrap();
+        let known = miners.insert(
+            name.clone(),
+            DownstreamMiner {
+                capacity_gb,
+                last_seen: Instant::now(),
+            },
+        );
+        let changed = known.map(|known| known.capacity_gb) != Some(capacity_gb);
+        drop(miners);
+        if changed {
+            info!(
+                "proxy: miner registered: name={}, capacity={}GiB",
+                name, capacity_gb
+            );
+            self.report_capacity();
+        }
+    }
+
+    fn mining_info(&self) -> Result<String, (StatusCode, i32, String)> {
+        match &*self.round.lock().unwrap() {
+            Some(round) => Ok(round.body.clone()),
+            None => Err(no_mining_info()),
+        }
+    }
+
+    fn submit_nonce(
+        &self,
+        params: &HashMap<String, String>,
+        headers: &HeaderMap,
+    ) -> Result<String, (StatusCode, i32, String)> {
+        let param = |name: &str| -> Result<u64, (StatusCode, i32, String)> {
+            params
+                .get(name)
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        1,
+                        format!("missing or invalid parameter: {}", name),
+                    )
+                })
+        };
+        let account_id = param("accountId")?;
+        let nonce = param("nonce")?;
+        let height = param("blockheight")?;
+
+        let (base_target, gen_sig, scoop, best_deadline) = {
+            let round = self.round.lock().unwrap();
+            let round = match &*round {
+                Some(round) => round,
+                None => return Err(no_mining_info()),
+            };
+            check_height(height, round.height)?;
+            let best_deadline = round.account_id_to_best_deadline.get(&account_id).cloned();
+            (round.base_target, round.gen_sig, round.scoop, best_deadline)
+        };
+
+        // the adjusted deadline comes in a header, legacy miners only send the unadjusted one
+        let deadline = headers
+            .get("X-Deadline")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .or_else(|| param("deadline").ok().map(|d| d / base_target))
+            .ok_or_else(|| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    1,
+                    "missing deadline, proxies need the deadline to rank submissions".to_owned(),
+                )
+            })?;
+        if deadline >= best_deadline.unwrap_or(u64::MAX) {
+            return Ok(
+                serde_json::json!({ "result": "success", "deadline": deadline }).to_string(),
+            );
+        }
+
+        // a claimed deadline could suppress the real best one of the account, the nonce is
+        // hashed again before it takes part in the ranking, outside of the lock
+        let deadline_unadjusted =
+            poc_hashing::calculate_deadline(account_id, nonce, scoop, &gen_sig);
+        if deadline_unadjusted / base_target != deadline {
+            warn!(
+                "{: <80}",
+                format!(
+                    "proxy: deadline mismatch: account={}, nonce={}, deadline_claimed={}, \
+                     deadline={}",
+                    Account(account_id),
+                    nonce,
+                    deadline,
+                    deadline_unadjusted / base_target
+                )
+            );
+            return Err((
+                StatusCode::BAD_REQUEST,
+                4,
+                format!(
+                    "deadline {} doesn't match the nonce, its deadline is {}",
+                    deadline,
+                    deadline_unadjusted / base_target
+                ),
+            ));
+        }
+
+        let mut round = self.round.lock().unwrap();
+        let round = match &mut *round {
+            Some(round) => round,
+            None => return Err(no_mining_info()),
+        };
+        // a new block may have come in while hashing
+        check_height(height, round.height)?;
+        let best_deadline = round
+            .account_id_to_best_deadline
+            .entry(account_id)
+            .or_insert(u64::MAX);
+        if deadline < *best_deadline {
+            *best_deadline = deadline;
+            info!(
+                "{: <80}",
+                format!(
+                    "proxy: forwarding deadline: account={}, nonce={}, deadline={}",
+                    Account(account_id),
+                    nonce,
+                    deadline
+                )
+            );
+            self.request_handler.submit_nonce(
+                account_id,
+                nonce,
+                height,
+                round.block,
+                deadline_unadjusted,
+                deadline,
+                round.gen_sig,
+            );
+        }
+
+        Ok(serde_json::json!({ "result": "success", "deadline": deadline }).to_string())
+    }
+}
+
+fn check_height(height: u64, current_height: u64) -> Result<(), (StatusCode, i32, String)> {
+    if height == current_height {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::BAD_REQUEST,
+            3,
+            format!(
+                "submission for height {}, current height is {}",
+                height, current_height
+            ),
+        ))
+    }
+}
+
+fn no_mining_info() -> (StatusCode, i32, String) {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        2,
+        "no mining info from upstream yet".to_owned(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::runtime::Runtime;
+
+    fn query(q: &str) -> HashMap<String, String> {
+        form_urlencoded::parse(q.as_bytes()).into_owned().collect()
+    }
+
+    #[test]
+    fn test_proxy() {
+        let rt = Runtime::new().unwrap();
+        let request_handler = RequestHandler::new(
+            vec!["http://127.0.0.1:1".parse().unwrap()],
+            HashMap::new(),
+            false,
+            1000,
+            10,
+            true,
+            HashMap::new(),
+            HashMap::new(),
+            rt.executor(),
+        );
+        let proxy = Proxy::new(request_handler, 10);
+        let headers = HeaderMap::new();
+
+        let (status, _) = proxy.respond(
+            &query("requestType=submitNonce&accountId=1&nonce=7&blockheight=100"),
+            &headers,
+        );
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        let (status, body) = proxy.respond(&query("requestType=getMiningInfo"), &headers);
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body.contains("error"));
+
+        let mining_info: MiningInfo = serde_json::from_str(
+            r#"{"generationSignature":"ab","baseTarget":"10","height":"100"}"#,
+        )
+        .unwrap();
+        proxy.update_mining_info(&mining_info, 1, [0; 32]);
+        let (status, body) = proxy.respond(&query("requestType=getMiningInfo"), &headers);
+        assert_eq!(status, StatusCode::OK);
+        let served: MiningInfo = serde_json::from_str(&body).unwrap();
+        assert_eq!(served.height, 100);
+        assert_eq!(served.base_target, 10);
+        assert_eq!(served.target_deadline, u64::MAX);
+
+        let submit = "requestType=submitNonce&accountId=1&nonce=7";
+        let (status, _) = proxy.respond(
+            &query(&format!("{}&blockheight=99&deadline=500", submit)),
+            &headers,
+        );
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // only better deadlines are forwarded, all are acknowledged
+        let scoop = poc_hashing::calculate_scoop(100, &[0; 32]);
+        let deadline = poc_hashing::calculate_deadline(1, 7, scoop, &[0; 32]);
+        let (status, body) = proxy.respond(
+            &query(&format!("{}&blockheight=100&deadline={}", submit, deadline)),
+            &headers,
+        );
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(&format!(r#""deadline":{}"#, deadline / 10)));
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Deadline", (deadline / 10 + 1).into());
+        let (status, _) = proxy.respond(&query(&format!("{}&blockheight=100", submit)), &headers);
+        assert_eq!(status, StatusCode::OK);
+
+        // claimed deadlines that don't match the nonce are turned down
+        headers.insert("X-Deadline", 0.into());
+        let (status, body) = proxy.respond(
+            &query("requestType=submitNonce&accountId=1&nonce=8&blockheight=100"),
+            &headers,
+        );
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("doesn't match the nonce"));
+        {
+            let round = proxy.round.lock().unwrap();
+            let best_deadline = round.as_ref().unwrap().account_id_to_best_deadline[&1];
+            assert_eq!(best_deadline, deadline / 10);
+        }
+
+        // downstream capacity is tracked per miner
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Minername", "rig".parse().unwrap());
+        headers.insert("X-Capacity", "20".parse().unwrap());
+        proxy.respond(&query("requestType=getMiningInfo"), &headers);
+        headers.insert("X-Capacity", "30".parse().unwrap());
+        proxy.respond(&query("requestType=getMiningInfo"), &headers);
+        assert_eq!(proxy.miners.lock().unwrap()["rig"].capacity_gb, 30);
+
+        rt.shutdown_now().wait().unwrap();
+    }
+}
@@ -1,14 +1,20 @@
+use crate::metrics;
 use crate::miner::Buffer;
 #[cfg(feature = "opencl")]
 use crate::miner::CpuBuffer;
 use crate::plot::{Meta, Plot};
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+use crate::reader_uring;
 use crate::utils::new_thread_pool;
 use crossbeam_channel;
 use crossbeam_channel::{Receiver, Sender};
 use pbr::{ProgressBar, Units};
-use rayon::prelude::*;
-use std::collections::HashMap;
+#[cfg(feature = "opencl")]
+use std::cmp::max;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
 use std::io::Stdout;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use stopwatch::Stopwatch;
 
@@ -21,6 +27,7 @@ pub struct BufferInfo {
     pub start_nonce: u64,
     pub finished: bool,
     pub account_id: u64,
+    pub chain: usize,
     pub gpu_signal: u64,
 }
 pub struct ReadReply {
@@ -28,24 +35,123 @@ pub struct ReadReply {
     pub info: BufferInfo,
 }
 
+/// A round of one chain, shared by the reader tasks of all drives.
+struct Round {
+    chain: usize,
+    height: u64,
+    block: u64,
+    base_target: u64,
+    scoop: u32,
+    gensig: Arc<[u8; 32]>,
+    // set once the chain moved on to the next block
+    interrupted: AtomicBool,
+    pb: Option<Arc<Mutex<pbr::ProgressBar<Stdout>>>>,
+}
+
+// a round as seen by one drive: the plots it started with and how far it got
+struct DriveRound {
+    round: Arc<Round>,
+    plots: Arc<Vec<Arc<Mutex<Plot>>>>,
+    next_plot: usize,
+    nonces_processed: u64,
+    elapsed: i64,
+}
+
+/// How far a drive got with the latest round of a chain, kept after the drive finished.
+#[derive(Clone, Serialize)]
+pub struct DriveProgress {
+    pub drive: String,
+    pub chain: usize,
+    pub height: u64,
+    pub plots: usize,
+    pub plots_read: usize,
+    // scoop bytes of all plots of the round
+    pub bytes: u64,
+    pub bytes_read: u64,
+    pub elapsed_ms: i64,
+    pub speed_mib_s: f64,
+    pub finished: bool,
+}
+
+impl DriveProgress {
+    fn update(&mut self, nonces_processed: u64, elapsed: i64) {
+        self.bytes_read = nonces_processed * 64;
+        self.elapsed_ms = elapsed;
+        self.speed_mib_s = self.bytes_read as f64 * 1000.0 / 1024.0 / 1024.0 / (elapsed + 1) as f64;
+    }
+}
+
+/// The rounds a drive has to read, a single reader task works through them plot by plot.
+#[derive(Default)]
+struct DriveQueue {
+    // by chain, chains configured first take priority
+    rounds: BTreeMap<usize, DriveRound>,
+    progress: BTreeMap<usize, DriveProgress>,
+    running: bool,
+}
+
+impl DriveQueue {
+    /// Takes the next plot of the round with the highest priority, along with whether it is the
+    /// round's last plot.
+    fn next_plot(&mut self) -> Option<(Arc<Round>, Arc<Mutex<Plot>>, bool)> {
+        let round = self
+            .rounds
+            .values_mut()
+            .find(|round| round.next_plot < round.plots.len())?;
+        let plot = round.plots[round.next_plot].clone();
+        round.next_plot += 1;
+        Some((
+            round.round.clone(),
+            plot,
+            round.next_plot == round.plots.len(),
+        ))
+    }
+}
+
+/// The drives of a reader, shared with the status server so that it doesn't have to wait for
+/// the reader.
+#[derive(Clone, Default)]
+pub struct Drives(Arc<Mutex<HashMap<String, Arc<Mutex<DriveQueue>>>>>);
+
+impl Drives {
+    /// The progress of every drive in the latest round of each chain.
+    pub fn progress(&self) -> Vec<DriveProgress> {
+        let queues: Vec<Arc<Mutex<DriveQueue>>> =
+            self.0.lock().unwrap().values().cloned().collect();
+        let mut drive_progress: Vec<DriveProgress> = queues
+            .iter()
+            .flat_map(|queue| {
+                let queue = queue.lock().unwrap();
+                queue.progress.values().cloned().collect::<Vec<_>>()
+            })
+            .collect();
+        drive_progress.sort_by(|a, b| (&a.drive, a.chain).cmp(&(&b.drive, b.chain)));
+        drive_progress
+    }
+}
+
 #[allow(dead_code)]
 pub struct Reader {
-    drive_id_to_plots: HashMap<String, Arc<Vec<Mutex<Plot>>>>,
+    drive_id_to_plots: HashMap<String, Arc<Vec<Arc<Mutex<Plot>>>>>,
     pub total_size: u64,
     pool: rayon::ThreadPool,
     rx_empty_buffers: Receiver<Box<dyn Buffer + Send>>,
     tx_empty_buffers: Sender<Box<dyn Buffer + Send>>,
     tx_read_replies_cpu: Sender<ReadReply>,
     tx_read_replies_gpu: Option<Vec<Sender<ReadReply>>>,
-    interupts: Vec<Sender<()>>,
+    drives: Drives,
+    rounds: HashMap<usize, Arc<Round>>,
+    // scoop bytes by drive, so that plots being read don't have to be locked to get their size
+    drive_bytes: HashMap<String, u64>,
     show_progress: bool,
     show_drive_stats: bool,
+    io_uring_queue_depth: usize,
 }
 
 impl Reader {
     pub fn new(
-        drive_id_to_plots: HashMap<String, Arc<Vec<Mutex<Plot>>>>,
-        total_size: u64,
+        drive_id_to_plots: HashMap<String, Arc<Vec<Arc<Mutex<Plot>>>>>,
+        drive_bytes: HashMap<String, u64>,
         num_threads: usize,
         rx_empty_buffers: Receiver<Box<dyn Buffer + Send>>,
         tx_empty_buffers: Sender<Box<dyn Buffer + Send>>,
@@ -55,6 +161,7 @@ impl Reader {
         show_drive_stats: bool,
         thread_pinning: bool,
         benchmark: bool,
+        io_uring_queue_depth: usize,
     ) -> Reader {
         if !benchmark {
             check_overlap(&drive_id_to_plots);
@@ -62,35 +169,84 @@ impl Reader {
 
         Reader {
             drive_id_to_plots,
-            total_size,
+            total_size: drive_bytes.values().sum(),
             pool: new_thread_pool(num_threads, thread_pinning),
             rx_empty_buffers,
             tx_empty_buffers,
             tx_read_replies_cpu,
             tx_read_replies_gpu,
-            interupts: Vec::new(),
+            drives: Drives::default(),
+            rounds: HashMap::new(),
+            drive_bytes,
             show_progress,
             show_drive_stats,
+            io_uring_queue_depth,
         }
     }
 
+    /// Replaces the plots read from the next round on. A round in progress keeps reading the
+    /// plots it started with.
+    pub fn update_plots(
+        &mut self,
+        drive_id_to_plots: HashMap<String, Arc<Vec<Arc<Mutex<Plot>>>>>,
+        drive_bytes: HashMap<String, u64>,
+    ) {
+        self.drive_id_to_plots = drive_id_to_plots;
+        self.total_size = drive_bytes.values().sum();
+        self.drive_bytes = drive_bytes;
+    }
+
+    /// Number of reader tasks that report back at the end of a round.
+    pub fn drive_count(&self) -> usize {
+        self.drive_id_to_plots.len()
+    }
+
+    /// The progress of every drive in the latest round of each chain.
+    pub fn drive_progress(&self) -> Vec<DriveProgress> {
+        self.drives.progress()
+    }
+
+    pub fn drives(&self) -> Drives {
+        self.drives.clone()
+    }
+
+    /// Starts a round of `chain`, its previous round is abandoned. Rounds of other chains go on,
+    /// drives read the plots of the chain with the lowest index first.
     pub fn start_reading(
         &mut self,
+        chain: usize,
         height: u64,
         block: u64,
         base_target: u64,
         scoop: u32,
         gensig: &Arc<[u8; 32]>,
     ) {
-        for interupt in &self.interupts {
-            interupt.send(()).ok();
+        if let Some(round) = self.rounds.get(&chain) {
+            round.interrupted.store(true, Ordering::SeqCst);
         }
-        let mut pb = ProgressBar::new(self.total_size);
-        pb.format("│██░│");
-        pb.set_width(Some(80));
-        pb.set_units(Units::Bytes);
-        pb.message("Scavenging: ");
-        let pb = Arc::new(Mutex::new(pb));
+
+        // bars of several chains would overwrite each other, so only the first one gets one
+        let pb = if self.show_progress && chain == 0 {
+            let mut pb = ProgressBar::new(self.total_size);
+            pb.format("│██░│");
+            pb.set_width(Some(80));
+            pb.set_units(Units::Bytes);
+            pb.message("Scavenging: ");
+            Some(Arc::new(Mutex::new(pb)))
+        } else {
+            None
+        };
+        let round = Arc::new(Round {
+            chain,
+            height,
+            block,
+            base_target,
+            scoop,
+            gensig: gensig.clone(),
+            interrupted: AtomicBool::new(false),
+            pb,
+        });
+        self.rounds.insert(chain, round.clone());
 
         // send start signals (dummy buffer) to gpu threads
         #[cfg(feature = "opencl")]
@@ -99,7 +255,8 @@ impl Reader {
                 .send(ReadReply {
                     buffer: Box::new(CpuBuffer::new(0)) as Box<dyn Buffer + Send>,
                     info: BufferInfo {
-                        len: 1,
+                        // the number of drives read in this round
+                        len: max(1, self.drive_id_to_plots.len()),
                         height,
                         block,
                         base_target,
@@ -107,46 +264,60 @@ impl Reader {
                         start_nonce: 0,
                         finished: false,
                         account_id: 0,
+                        chain,
                         gpu_signal: 1,
                     },
                 })
                 .expect("Error sending 'round start' signal to GPU");
         }
 
-        self.interupts = self
-            .drive_id_to_plots
-            .iter()
-            .map(|(drive, plots)| {
-                let (interupt, task) = if self.show_progress {
-                    self.create_read_task(
-                        Some(pb.clone()),
-                        drive.clone(),
-                        plots.clone(),
-                        height,
-                        block,
-                        base_target,
-                        scoop,
-                        gensig.clone(),
-                        self.show_drive_stats,
-                    )
-                } else {
-                    self.create_read_task(
-                        None,
-                        drive.clone(),
-                        plots.clone(),
-                        height,
-                        block,
-                        base_target,
-                        scoop,
-                        gensig.clone(),
-                        self.show_drive_stats,
-                    )
-                };
+        let mut drives = self.drives.0.lock().unwrap();
+        for (drive, plots) in &self.drive_id_to_plots {
+            let queue = drives.entry(drive.clone()).or_default().clone();
+            let mut drive_queue = queue.lock().unwrap();
+            drive_queue.rounds.insert(
+                chain,
+                DriveRound {
+                    round: round.clone(),
+                    plots: plots.clone(),
+                    next_plot: 0,
+                    nonces_processed: 0,
+                    elapsed: 0,
+                },
+            );
+            drive_queue.progress.insert(
+                chain,
+                DriveProgress {
+                    drive: drive.clone(),
+                    chain,
+                    height,
+                    plots: plots.len(),
+                    plots_read: 0,
+                    bytes: self.drive_bytes.get(drive).copied().unwrap_or(0),
+                    bytes_read: 0,
+                    elapsed_ms: 0,
+                    speed_mib_s: 0.0,
+                    finished: false,
+                },
+            );
+            if !drive_queue.running {
+                drive_queue.running = true;
+                self.pool
+                    .spawn(self.create_read_task(drive.clone(), queue.clone()));
+            }
+        }
 
-                self.pool.spawn(task);
-                interupt
-            })
-            .collect();
+        // drives without plots since the last update only drop their old round of this chain
+        let drive_id_to_plots = &self.drive_id_to_plots;
+        drives.retain(|drive, queue| {
+            if drive_id_to_plots.contains_key(drive) {
+                return true;
+            }
+            let mut queue = queue.lock().unwrap();
+            queue.rounds.remove(&chain);
+            queue.progress.remove(&chain);
+            !queue.rounds.is_empty()
+        });
     }
 
     pub fn wakeup(&mut self) {
@@ -165,201 +336,505 @@ impl Reader {
         }
     }
 
-    fn create_read_task(
-        &self,
-        pb: Option<Arc<Mutex<pbr::ProgressBar<Stdout>>>>,
-        drive: String,
-        plots: Arc<Vec<Mutex<Plot>>>,
-        height: u64,
-        block: u64,
-        base_target: u64,
-        scoop: u32,
-        gensig: Arc<[u8; 32]>,
-        show_drive_stats: bool,
-    ) -> (Sender<()>, impl FnOnce()) {
-        let (tx_interupt, rx_interupt) = crossbeam_channel::unbounded();
+    fn create_read_task(&self, drive: String, queue: Arc<Mutex<DriveQueue>>) -> impl FnOnce() {
         let rx_empty_buffers = self.rx_empty_buffers.clone();
         let tx_empty_buffers = self.tx_empty_buffers.clone();
         let tx_read_replies_cpu = self.tx_read_replies_cpu.clone();
         #[cfg(feature = "opencl")]
         let tx_read_replies_gpu = self.tx_read_replies_gpu.clone();
+        let show_drive_stats = self.show_drive_stats;
+
+        #[cfg(all(feature = "io_uring", target_os = "linux"))]
+        let io_uring_queue_depth = self.io_uring_queue_depth;
 
-        (tx_interupt, move || {
+        move || {
             let mut sw = Stopwatch::new();
-            let mut elapsed = 0i64;
-            let mut nonces_processed = 0u64;
-            let plot_count = plots.len();
-            'outer: for (i_p, p) in plots.iter().enumerate() {
+
+            let send = |round: &Round,
+                        buffer: Box<dyn Buffer + Send>,
+                        bytes_read: usize,
+                        start_nonce: u64,
+                        finished: bool,
+                        account_id: u64| {
+                let info = BufferInfo {
+                    len: bytes_read,
+                    height: round.height,
+                    block: round.block,
+                    base_target: round.base_target,
+                    gensig: round.gensig.clone(),
+                    start_nonce,
+                    finished,
+                    account_id,
+                    chain: round.chain,
+                    gpu_signal: 0,
+                };
+                // buffer routing
+                #[cfg(feature = "opencl")]
+                match buffer.get_id() {
+                    0 => {
+                        tx_read_replies_cpu
+                            .send(ReadReply { buffer, info })
+                            .expect("failed to send read data to CPU thread");
+                    }
+                    i => {
+                        tx_read_replies_gpu.as_ref().unwrap()[i - 1]
+                            .send(ReadReply { buffer, info })
+                            .expect("failed to send read data to GPU thread A");
+                    }
+                }
+                #[cfg(not(feature = "opencl"))]
+                tx_read_replies_cpu
+                    .send(ReadReply { buffer, info })
+                    .unwrap();
+
+                if let Some(pb) = &round.pb {
+                    pb.lock().unwrap().add(bytes_read as u64);
+                }
+
+                // send termination signal (dummy buffer) to gpu
+                if finished {
+                    #[cfg(feature = "opencl")]
+                    for i in 0..tx_read_replies_gpu.as_ref().unwrap().len() {
+                        tx_read_replies_gpu.as_ref().unwrap()[i]
+                            .send(ReadReply {
+                                buffer: Box::new(CpuBuffer::new(0)) as Box<dyn Buffer + Send>,
+                                info: BufferInfo {
+                                    len: 1,
+                                    height: round.height,
+                                    block: round.block,
+                                    base_target: round.base_target,
+                                    gensig: round.gensig.clone(),
+                                    start_nonce: 0,
+                                    finished: false,
+                                    account_id: 0,
+                                    chain: round.chain,
+                                    gpu_signal: 2,
+                                },
+                            })
+                            .expect("Error sending 'drive finished' signal to GPU thread A");
+                    }
+                }
+            };
+
+            // the progress of the plot being read, on top of the plots read before
+            let report = |round: &Arc<Round>, nonces_processed: u64, elapsed: i64| {
+                let mut queue = queue.lock().unwrap();
+                let queue = &mut *queue;
+                let drive_round = queue
+                    .rounds
+                    .get(&round.chain)
+                    .filter(|drive_round| Arc::ptr_eq(&drive_round.round, round));
+                if let (Some(drive_round), Some(progress)) =
+                    (drive_round, queue.progress.get_mut(&round.chain))
+                {
+                    progress.update(
+                        drive_round.nonces_processed + nonces_processed,
+                        drive_round.elapsed + elapsed,
+                    );
+                }
+            };
+
+            // a ring per reader task, if io_uring isn't available the blocking reads are used
+            #[cfg(all(feature = "io_uring", target_os = "linux"))]
+            let mut ring = if io_uring_queue_depth > 0 {
+                match reader_uring::new_ring(io_uring_queue_depth) {
+                    Ok(ring) => Some(ring),
+                    Err(e) => {
+                        warn!("reader: io_uring unavailable, using blocking reads: {}", e);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
+            loop {
+                let (round, p, last_plot) = {
+                    let mut queue = queue.lock().unwrap();
+                    match queue.next_plot() {
+                        Some(next) => next,
+                        None => {
+                            queue.running = false;
+                            return;
+                        }
+                    }
+                };
+                // a new round cancels the read between chunks, see the check below
+                let interrupted = || round.interrupted.load(Ordering::SeqCst);
+                if interrupted() {
+                    continue;
+                }
+                let mut elapsed = 0i64;
+                let mut nonces_processed = 0u64;
+                let mut drive_finished = false;
+
                 let mut p = p.lock().unwrap();
-                if let Err(e) = p.prepare(scoop) {
+                if let Err(e) = p.prepare(round.scoop) {
                     error!(
                         "reader: error preparing {} for reading: {} -> skip one round",
                         p.meta.name, e
                     );
-                    continue 'outer;
+                    metrics::PLOT_READ_ERRORS.inc(&[("plot", &p.meta.name)]);
+                    continue;
                 }
 
-                'inner: for mut buffer in rx_empty_buffers.clone() {
-                    if show_drive_stats {
+                // PoC1 plots and dummies are left to the blocking reads
+                #[cfg(all(feature = "io_uring", target_os = "linux"))]
+                let mut use_ring = ring.is_some() && p.meta.stagger.is_none() && !p.is_dummy();
+                #[cfg(not(all(feature = "io_uring", target_os = "linux")))]
+                let use_ring = false;
+
+                #[cfg(all(feature = "io_uring", target_os = "linux"))]
+                {
+                    if use_ring {
                         sw.restart();
-                    }
-                    let mut_bs = buffer.get_buffer_for_writing();
-                    let mut bs = mut_bs.lock().unwrap();
-                    let (bytes_read, start_nonce, next_plot) = match p.read(&mut bs, scoop) {
-                        Ok(x) => x,
-                        Err(e) => {
+                        let account_id = p.meta.account_id;
+                        let read = reader_uring::read_plot(
+                            ring.as_mut().unwrap(),
+                            &mut p,
+                            round.scoop,
+                            io_uring_queue_depth,
+                            &rx_empty_buffers,
+                            &tx_empty_buffers,
+                            &interrupted,
+                            &mut |buffer, bytes_read, start_nonce, next_plot| {
+                                let finished = last_plot && next_plot;
+                                send(
+                                    &round,
+                                    buffer,
+                                    bytes_read,
+                                    start_nonce,
+                                    finished,
+                                    account_id,
+                                );
+                                nonces_processed += bytes_read as u64 / 64;
+                                drive_finished |= finished;
+                                report(&round, nonces_processed, sw.elapsed_ms());
+                            },
+                        );
+                        elapsed += sw.elapsed_ms();
+                        // the rest of the plot is read blocking, the ring is replaced for the
+                        // following plots
+                        if let Err(e) = read {
                             error!(
-                                "reader: error reading chunk from {}: {} -> skip one round",
+                                "reader: error reading {} with io_uring: {} -> using blocking reads",
                                 p.meta.name, e
                             );
-                            buffer.unmap();
-                            (0, 0, true)
-                        }
-                    };
-
-                    if rx_interupt.try_recv().is_ok() {
-                        buffer.unmap();
-                        tx_empty_buffers.send(buffer).unwrap();
-                        break 'outer;
-                    }
-
-                    let finished = i_p == (plot_count - 1) && next_plot;
-                    // buffer routing
-                    #[cfg(feature = "opencl")]
-                    match buffer.get_id() {
-                        0 => {
-                            tx_read_replies_cpu
-                                .send(ReadReply {
-                                    buffer,
-                                    info: BufferInfo {
-                                        len: bytes_read,
-                                        height,
-                                        block,
-                                        base_target,
-                                        gensig: gensig.clone(),
-                                        start_nonce,
-                                        finished,
-                                        account_id: p.meta.account_id,
-                                        gpu_signal: 0,
-                                    },
-                                })
-                                .expect("failed to send read data to CPU thread");
-                        }
-                        i => {
-                            tx_read_replies_gpu.as_ref().unwrap()[i - 1]
-                                .send(ReadReply {
-                                    buffer,
-                                    info: BufferInfo {
-                                        len: bytes_read,
-                                        height,
-                                        block,
-                                        base_target,
-                                        gensig: gensig.clone(),
-                                        start_nonce,
-                                        finished,
-                                        account_id: p.meta.account_id,
-                                        gpu_signal: 0,
-                                    },
-                                })
-                                .expect("failed to send read data to GPU thread A");
+                            metrics::PLOT_READ_ERRORS.inc(&[("plot", &p.meta.name)]);
+                            ring = match reader_uring::new_ring(io_uring_queue_depth) {
+                                Ok(ring) => Some(ring),
+                                Err(e) => {
+                                    warn!(
+                                        "reader: io_uring unavailable, using blocking reads: {}",
+                                        e
+                                    );
+                                    None
+                                }
+                            };
+                            use_ring = false;
                         }
                     }
-                    #[cfg(not(feature = "opencl"))]
-                    tx_read_replies_cpu
-                        .send(ReadReply {
-                            buffer,
-                            info: BufferInfo {
-                                len: bytes_read,
-                                height,
-                                block,
-                                base_target,
-                                gensig: gensig.clone(),
-                                start_nonce,
-                                finished,
-                                account_id: p.meta.account_id,
-                                gpu_signal: 0,
-                            },
-                        })
-                        .unwrap();
+                }
 
-                    nonces_processed += bytes_read as u64 / 64;
+                if !use_ring {
+                    for mut buffer in rx_empty_buffers.clone() {
+                        sw.restart();
+                        let mut_bs = buffer.get_buffer_for_writing();
+                        let mut bs = mut_bs.lock().unwrap();
+                        let read = p.read_interruptible(&mut bs, round.scoop, &interrupted);
+                        let (bytes_read, start_nonce, next_plot) = match read {
+                            Ok(x) => x,
+                            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (0, 0, true),
+                            Err(e) => {
+                                error!(
+                                    "reader: error reading chunk from {}: {} -> skip one round",
+                                    p.meta.name, e
+                                );
+                                metrics::PLOT_READ_ERRORS.inc(&[("plot", &p.meta.name)]);
+                                buffer.unmap();
+                                (0, 0, true)
+                            }
+                        };
+                        drop(bs);
 
-                    match &pb {
-                        Some(pb) => {
-                            let mut pb = pb.lock().unwrap();
-                            pb.add(bytes_read as u64);
+                        if interrupted() {
+                            buffer.unmap();
+                            tx_empty_buffers.send(buffer).unwrap();
+                            break;
                         }
-                        None => (),
-                    }
 
-                    if show_drive_stats {
+                        let finished = last_plot && next_plot;
+                        send(
+                            &round,
+                            buffer,
+                            bytes_read,
+                            start_nonce,
+                            finished,
+                            p.meta.account_id,
+                        );
+                        nonces_processed += bytes_read as u64 / 64;
+                        drive_finished |= finished;
                         elapsed += sw.elapsed_ms();
-                    }
+                        report(&round, nonces_processed, elapsed);
 
-                    // send termination signal (dummy buffer) to gpu
-                    if finished {
-                        #[cfg(feature = "opencl")]
-                        for i in 0..tx_read_replies_gpu.as_ref().unwrap().len() {
-                            tx_read_replies_gpu.as_ref().unwrap()[i]
-                                .send(ReadReply {
-                                    buffer: Box::new(CpuBuffer::new(0)) as Box<dyn Buffer + Send>,
-                                    info: BufferInfo {
-                                        len: 1,
-                                        height,
-                                        block,
-                                        base_target,
-                                        gensig: gensig.clone(),
-                                        start_nonce: 0,
-                                        finished: false,
-                                        account_id: 0,
-                                        gpu_signal: 2,
-                                    },
-                                })
-                                .expect("Error sending 'drive finished' signal to GPU thread A");
+                        if next_plot {
+                            break;
                         }
                     }
+                }
+                drop(p);
+                metrics::DRIVE_READ_BYTES.add(&[("drive", &drive)], (nonces_processed * 64) as f64);
 
-                    if finished && show_drive_stats {
-                        info!(
+                let mut queue = queue.lock().unwrap();
+                let queue = &mut *queue;
+                let chain = round.chain;
+                if let Some(drive_round) = queue
+                    .rounds
+                    .get_mut(&chain)
+                    .filter(|drive_round| Arc::ptr_eq(&drive_round.round, &round))
+                {
+                    drive_round.nonces_processed += nonces_processed;
+                    drive_round.elapsed += elapsed;
+                    if let Some(progress) = queue.progress.get_mut(&chain) {
+                        progress.update(drive_round.nonces_processed, drive_round.elapsed);
+                        progress.plots_read += 1;
+                        progress.finished = drive_finished;
+                    }
+                    if drive_finished {
+                        let speed = drive_round.nonces_processed * 1000
+                            / (drive_round.elapsed + 1) as u64
+                            * 64
+                            / 1024
+                            / 1024;
+                        // structured logs always get the event, the console only if asked for
+                        let level = if show_drive_stats {
+                            log::Level::Info
+                        } else {
+                            log::Level::Debug
+                        };
+                        log!(
+                            level,
+                            drive = drive.as_str(),
+                            elapsed_ms = drive_round.elapsed,
+                            speed_mib_s = speed;
                             "{: <80}",
-                            format!(
-                                "drive {} finished, speed={} MiB/s",
-                                drive,
-                                nonces_processed * 1000 / (elapsed + 1) as u64 * 64 / 1024 / 1024,
-                            )
+                            format!("drive {} finished, speed={} MiB/s", drive, speed)
                         );
-                    }
-
-                    if next_plot {
-                        break 'inner;
+                        queue.rounds.remove(&chain);
                     }
                 }
             }
-        })
+        }
     }
 }
 
 // Don't waste your time striving for perfection; instead, strive for excellence - doing your best.
 // let my_best = perfection;
-pub fn check_overlap(drive_id_to_plots: &HashMap<String, Arc<Vec<Mutex<Plot>>>>) -> bool {
-    let plots: Vec<Meta> = drive_id_to_plots
+pub fn check_overlap(drive_id_to_plots: &HashMap<String, Arc<Vec<Arc<Mutex<Plot>>>>>) -> bool {
+    let mut plots: Vec<Meta> = drive_id_to_plots
         .values()
         .map(|a| a.iter())
         .flatten()
         .map(|plot| plot.lock().unwrap().meta.clone())
         .collect();
-    plots
-        .par_iter()
-        .enumerate()
-        .filter(|(i, plot_a)| {
-            plots[i + 1..]
-                .par_iter()
-                .filter(|plot_b| {
-                    plot_a.account_id == plot_b.account_id && plot_b.overlaps_with(&plot_a)
+
+    // once sorted only the following plots that start before the end of a plot can overlap it
+    plots.sort_by_key(|plot| (plot.account_id, plot.start_nonce));
+    let mut overlap = false;
+    for (i, plot_a) in plots.iter().enumerate() {
+        for plot_b in plots[i + 1..].iter().take_while(|plot_b| {
+            plot_b.account_id == plot_a.account_id
+                && plot_b.start_nonce < plot_a.start_nonce + plot_a.nonces
+        }) {
+            overlap |= plot_b.overlaps_with(plot_a);
+        }
+    }
+    overlap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miner::CpuBuffer;
+    use crate::plot::NONCE_SIZE;
+    use std::env;
+    use std::fs;
+    use std::iter;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_check_overlap() {
+        let dir = env::temp_dir().join("scavenger_test_overlap");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let plots = |names: &[&str]| {
+            let plots: Vec<Arc<Mutex<Plot>>> = names
+                .iter()
+                .map(|name| {
+                    let path = dir.join(name);
+                    let nonces: u64 = name.split('_').nth(2).unwrap().parse().unwrap();
+                    fs::write(&path, vec![0u8; (nonces * NONCE_SIZE) as usize]).unwrap();
+                    Arc::new(Mutex::new(Plot::new(&path, false, false).unwrap()))
                 })
-                .count()
-                > 0
-        })
-        .count()
-        > 0
+                .collect();
+            let mut drive_id_to_plots = HashMap::new();
+            drive_id_to_plots.insert("drive".to_owned(), Arc::new(plots));
+            drive_id_to_plots
+        };
+
+        let disjoint = plots(&["1_2_1", "1_0_2", "2_1_1", "1_3_2"]);
+        assert!(!check_overlap(&disjoint));
+        assert!(check_overlap(&plots(&["1_2_1", "1_0_2", "2_1_1", "1_1_1"])));
+        assert!(check_overlap(&plots(&["1_0_4", "1_3_1"])));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_round_switch() {
+        let dir = env::temp_dir().join("scavenger_test_round_switch");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        // a sparse plot with scoops of 384 MiB, read in three buffers of 128 MiB
+        let nonces = 384 * 1024 * 1024 / 64;
+        let path = dir.join(format!("1_0_{}", nonces));
+        fs::File::create(&path)
+            .unwrap()
+            .set_len(nonces * NONCE_SIZE)
+            .unwrap();
+        let plot = Arc::new(Mutex::new(Plot::new(&path, false, false).unwrap()));
+        let mut drive_id_to_plots = HashMap::new();
+        drive_id_to_plots.insert("drive".to_owned(), Arc::new(vec![plot]));
+
+        let (tx_empty_buffers, rx_empty_buffers) = crossbeam_channel::unbounded();
+        let (tx_read_replies, rx_read_replies) = crossbeam_channel::unbounded();
+        let mut reader = Reader::new(
+            drive_id_to_plots,
+            iter::once(("drive".to_owned(), nonces * 64)).collect(),
+            1,
+            rx_empty_buffers,
+            tx_empty_buffers.clone(),
+            tx_read_replies,
+            None,
+            false,
+            false,
+            false,
+            true,
+            0,
+        );
+        tx_empty_buffers
+            .send(Box::new(CpuBuffer::new(128 * 1024 * 1024)) as Box<dyn Buffer + Send>)
+            .unwrap();
+
+        // the first read maps the buffer's pages, the second one shows how long a read takes
+        let gensig = Arc::new([0u8; 32]);
+        reader.start_reading(0, 1, 1, 1, 0, &gensig);
+        let reply = rx_read_replies.recv().unwrap();
+        let sw = Stopwatch::start_new();
+        tx_empty_buffers.send(reply.buffer).unwrap();
+        let reply = rx_read_replies.recv().unwrap();
+        let full_read_ms = sw.elapsed_ms();
+        assert_eq!(reply.info.height, 1);
+
+        // the next round reads a small plot, so that it replies as soon as the read of the
+        // third buffer in progress gives up
+        tx_empty_buffers.send(reply.buffer).unwrap();
+        thread::sleep(Duration::from_millis(full_read_ms as u64 / 4));
+        let path = dir.join("2_0_1");
+        fs::write(&path, vec![0u8; NONCE_SIZE as usize]).unwrap();
+        let plot = Arc::new(Mutex::new(Plot::new(&path, false, false).unwrap()));
+        let mut drive_id_to_plots = HashMap::new();
+        drive_id_to_plots.insert("drive".to_owned(), Arc::new(vec![plot]));
+        let sw = Stopwatch::start_new();
+        reader.update_plots(
+            drive_id_to_plots,
+            iter::once(("drive".to_owned(), 64)).collect(),
+        );
+        reader.start_reading(0, 2, 2, 1, 1, &gensig);
+
+        let reply = rx_read_replies
+            .recv_timeout(Duration::from_secs(5))
+            .expect("new round didn't start");
+        assert_eq!(reply.info.height, 2);
+        assert!(reply.info.finished);
+        assert!(
+            sw.elapsed_ms() < full_read_ms / 2,
+            "read in progress wasn't interrupted: {} ms of {} ms",
+            sw.elapsed_ms(),
+            full_read_ms
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_chains() {
+        let dir = env::temp_dir().join("scavenger_test_chains");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        // two plots with scoops of two nonces, read one nonce at a time
+        let path = dir.join("1_0_2");
+        fs::write(&path, vec![0u8; 2 * NONCE_SIZE as usize]).unwrap();
+        let plot = Arc::new(Mutex::new(Plot::new(&path, false, false).unwrap()));
+        let mut drive_id_to_plots = HashMap::new();
+        drive_id_to_plots.insert("drive".to_owned(), Arc::new(vec![plot; 2]));
+
+        let (tx_empty_buffers, rx_empty_buffers) = crossbeam_channel::unbounded();
+        let (tx_read_replies, rx_read_replies) = crossbeam_channel::unbounded();
+        let mut reader = Reader::new(
+            drive_id_to_plots,
+            iter::once(("drive".to_owned(), 2 * 2 * 64)).collect(),
+            1,
+            rx_empty_buffers,
+            tx_empty_buffers.clone(),
+            tx_read_replies,
+            None,
+            false,
+            false,
+            false,
+            true,
+            0,
+        );
+        tx_empty_buffers
+            .send(Box::new(CpuBuffer::new(64)) as Box<dyn Buffer + Send>)
+            .unwrap();
+
+        let gensig = Arc::new([0u8; 32]);
+        reader.start_reading(1, 10, 1, 1, 0, &gensig);
+        let reply = rx_read_replies.recv().unwrap();
+        assert_eq!(reply.info.chain, 1);
+
+        // the first chain takes over once the plot in progress is read, without ending the
+        // round of the other one
+        reader.start_reading(0, 20, 1, 1, 5, &gensig);
+        let mut replies = vec![(reply.info.chain, reply.info.height, reply.info.finished)];
+        let mut buffer = reply.buffer;
+        while replies.iter().filter(|reply| reply.2).count() < 2 {
+            tx_empty_buffers.send(buffer).unwrap();
+            let reply = rx_read_replies
+                .recv_timeout(Duration::from_secs(5))
+                .expect("round didn't finish");
+            replies.push((reply.info.chain, reply.info.height, reply.info.finished));
+            buffer = reply.buffer;
+        }
+        assert_eq!(
+            replies,
+            vec![
+                (1, 10, false),
+                (1, 10, false),
+                (0, 20, false),
+                (0, 20, false),
+                (0, 20, false),
+                (0, 20, true),
+                (1, 10, false),
+                (1, 10, true),
+            ]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
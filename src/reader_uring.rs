@@ -0,0 +1,350 @@
+use crate::metrics;
+use crate::miner::Buffer;
+use crate::plot::{Plot, ReadPlan};
+use crossbeam_channel::{Receiver, Sender};
+use io_uring::{opcode, types, IoUring};
+use std::collections::VecDeque;
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+// a read into a buffer, split into up to two submissions (direct and buffered part)
+struct InFlight {
+    buffer: Box<dyn Buffer + Send>,
+    plan: ReadPlan,
+    pending: u32,
+    result: io::Result<()>,
+}
+
+/// Creates a ring that fits `queue_depth` reads of a drive.
+pub fn new_ring(queue_depth: usize) -> io::Result<IoUring> {
+    IoUring::new((queue_depth * 2).next_power_of_two() as u32)
+}
+
+/// Reads the scoop of a prepared PoC2 plot keeping up to `queue_depth` reads in flight. Buffers
+/// are handed to `reply` in plot order as `(buffer, len, start_nonce, next_plot)` like the
+/// blocking reader does. Returns true if the round was interrupted. If the ring fails, the
+/// reads in flight are waited for and an error is returned, the plot then continues with the
+/// first read that wasn't handed out and the ring must be replaced.
+pub fn read_plot(
+    ring: &mut IoUring,
+    plot: &mut Plot,
+    scoop: u32,
+    queue_depth: usize,
+    rx_empty_buffers: &Receiver<Box<dyn Buffer + Send>>,
+    tx_empty_buffers: &Sender<Box<dyn Buffer + Send>>,
+    interrupted: &dyn Fn() -> bool,
+    reply: &mut dyn FnMut(Box<dyn Buffer + Send>, usize, u64, bool),
+) -> io::Result<bool> {
+    let (direct_fd, buffered_fd) = plot.raw_fds();
+    let mut in_flight: VecDeque<InFlight> = VecDeque::with_capacity(queue_depth);
+    // id of the read at the front of the queue, ids are used as user data
+    let mut front_id = 0u64;
+    let mut planned_all = false;
+    let mut failed = false;
+    let mut round_interrupted = false;
+
+    loop {
+        // queue reads as long as there are empty buffers, only block if nothing is in flight
+        while !planned_all && !failed && !round_interrupted && in_flight.len() < queue_depth {
+            let mut buffer = if in_flight.is_empty() {
+                match rx_empty_buffers.recv() {
+                    Ok(buffer) => buffer,
+                    Err(_) => return Ok(true),
+                }
+            } else {
+                match rx_empty_buffers.try_recv() {
+                    Ok(buffer) => buffer,
+                    Err(_) => break,
+                }
+            };
+
+            let mut_bs = buffer.get_buffer_for_writing();
+            let (ptr, cap) = {
+                let mut bs = mut_bs.lock().unwrap();
+                (bs.as_mut_ptr(), bs.capacity())
+            };
+            let plan = plot.plan_read(cap as u64, scoop);
+            let id = front_id + in_flight.len() as u64;
+
+            let mut pending = 0;
+            let mut error = None;
+            let parts = [
+                (direct_fd, 0, plan.direct_bytes),
+                (buffered_fd, plan.direct_bytes, plan.buffered_bytes),
+            ];
+            for (part, &(fd, offset, len)) in parts.iter().enumerate() {
+                if len == 0 {
+                    continue;
+                }
+                let entry = opcode::Read::new(
+                    types::Fd(fd),
+                    unsafe { ptr.add(offset as usize) },
+                    len as u32,
+                )
+                .offset64((plan.addr + offset) as i64)
+                .build()
+                .user_data(id << 1 | part as u64);
+                // the buffer stays in the queue until the kernel is done with it and the ring
+                // has room for two entries per read
+                if unsafe { ring.submission().push(&entry) }.is_err() {
+                    error = Some(io::Error::other("io_uring submission queue is full"));
+                    break;
+                }
+                pending += 1;
+            }
+
+            planned_all = plan.finished;
+            in_flight.push_back(InFlight {
+                buffer,
+                plan,
+                pending,
+                result: Ok(()),
+            });
+            if let Some(e) = error {
+                abort(ring, plot, in_flight, front_id, tx_empty_buffers, !failed);
+                return if failed || round_interrupted {
+                    Ok(round_interrupted)
+                } else {
+                    Err(e)
+                };
+            }
+        }
+
+        if in_flight.is_empty() {
+            return Ok(round_interrupted);
+        }
+
+        let want = usize::from(in_flight.front().unwrap().pending > 0);
+        loop {
+            match ring.submit_and_wait(want) {
+                Ok(_) => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    abort(ring, plot, in_flight, front_id, tx_empty_buffers, !failed);
+                    return if failed || round_interrupted {
+                        Ok(round_interrupted)
+                    } else {
+                        Err(e)
+                    };
+                }
+            }
+        }
+
+        complete(ring, &mut in_flight, front_id);
+
+        // hand out finished reads in order
+        while let Some(read) = in_flight.pop_front() {
+            if read.pending > 0 {
+                in_flight.push_front(read);
+                break;
+            }
+            front_id += 1;
+
+            round_interrupted |= interrupted();
+            if round_interrupted || failed {
+                read.buffer.unmap();
+                tx_empty_buffers.send(read.buffer).unwrap();
+                continue;
+            }
+
+            match read.result {
+                Ok(()) => reply(
+                    read.buffer,
+                    read.plan.len(),
+                    read.plan.start_nonce,
+                    read.plan.finished,
+                ),
+                Err(e) => {
+                    error!(
+                        "reader: error reading chunk from {}: {} -> skip one round",
+                        plot.meta.name, e
+                    );
+                    metrics::PLOT_READ_ERRORS.inc(&[("plot", &plot.meta.name)]);
+                    read.buffer.unmap();
+                    failed = true;
+                    reply(read.buffer, 0, 0, true);
+                }
+            }
+        }
+    }
+}
+
+// collects the completions of reads in flight
+fn complete(ring: &mut IoUring, in_flight: &mut VecDeque<InFlight>, front_id: u64) {
+    for cqe in ring.completion() {
+        let read = &mut in_flight[((cqe.user_data() >> 1) - front_id) as usize];
+        let expected = if cqe.user_data() & 1 == 0 {
+            read.plan.direct_bytes
+        } else {
+            read.plan.buffered_bytes
+        };
+        read.pending -= 1;
+        if cqe.result() < 0 {
+            read.result = Err(io::Error::from_raw_os_error(-cqe.result()));
+        } else if cqe.result() as u64 != expected && read.result.is_ok() {
+            read.result = Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "failed to fill whole buffer",
+            ));
+        }
+    }
+}
+
+// Waits for the reads the kernel took, entries left in the submission queue are never
+// completed. The buffers are returned empty and the plot is rewound to the first of them.
+fn abort(
+    ring: &mut IoUring,
+    plot: &mut Plot,
+    mut in_flight: VecDeque<InFlight>,
+    front_id: u64,
+    tx_empty_buffers: &Sender<Box<dyn Buffer + Send>>,
+    rewind: bool,
+) {
+    loop {
+        complete(ring, &mut in_flight, front_id);
+        let pending: usize = in_flight.iter().map(|read| read.pending as usize).sum();
+        if pending <= ring.submission().len() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    if let Some(read) = in_flight.front().filter(|_| rewind) {
+        plot.replan(&read.plan);
+    }
+    for read in in_flight {
+        read.buffer.unmap();
+        tx_empty_buffers.send(read.buffer).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miner::CpuBuffer;
+    use crate::plot::{NONCE_SIZE, SCOOP_SIZE};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_read_plot() {
+        let mut ring = match new_ring(3) {
+            Ok(ring) => ring,
+            Err(e) => {
+                eprintln!("io_uring unavailable, skipping: {}", e);
+                return;
+            }
+        };
+
+        let dir = env::temp_dir().join("scavenger_test_uring");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let nonces = 67;
+        let data: Vec<u8> = (0..nonces * NONCE_SIZE).map(|i| (i % 251) as u8).collect();
+        let path = dir.join(format!("1_100_{}", nonces));
+        fs::write(&path, &data).unwrap();
+
+        let (tx_empty_buffers, rx_empty_buffers) = crossbeam_channel::unbounded();
+        for _ in 0..4 {
+            tx_empty_buffers
+                .send(Box::new(CpuBuffer::new(8192)) as Box<dyn Buffer + Send>)
+                .unwrap();
+        }
+
+        for &use_direct_io in &[false, true] {
+            let mut plot = Plot::new(&path, use_direct_io, false).unwrap();
+            let scoop = 7;
+            plot.prepare(scoop).unwrap();
+
+            let mut read = Vec::new();
+            let mut finished = false;
+            let interrupted = read_plot(
+                &mut ring,
+                &mut plot,
+                scoop,
+                3,
+                &rx_empty_buffers,
+                &tx_empty_buffers,
+                &|| false,
+                &mut |mut buffer, len, start_nonce, next_plot| {
+                    assert!(!finished);
+                    assert_eq!(start_nonce, 100 + read.len() as u64 / SCOOP_SIZE);
+                    let bs = buffer.get_buffer();
+                    read.extend_from_slice(&bs.lock().unwrap()[..len]);
+                    finished = next_plot;
+                    tx_empty_buffers.send(buffer).unwrap();
+                },
+            )
+            .unwrap();
+
+            assert!(!interrupted);
+            assert!(finished);
+            let scoop_size = (nonces * SCOOP_SIZE) as usize;
+            let offset = scoop as usize * scoop_size;
+            assert!(read == data[offset..offset + scoop_size].to_vec());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_plot_fallback() {
+        // a ring too small for the queue depth fails to queue the third read
+        let mut ring = match new_ring(1) {
+            Ok(ring) => ring,
+            Err(e) => {
+                eprintln!("io_uring unavailable, skipping: {}", e);
+                return;
+            }
+        };
+
+        let dir = env::temp_dir().join("scavenger_test_uring_fallback");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let nonces = 1024;
+        let data: Vec<u8> = (0..nonces * NONCE_SIZE).map(|i| (i % 251) as u8).collect();
+        let path = dir.join(format!("1_0_{}", nonces));
+        fs::write(&path, &data).unwrap();
+
+        let (tx_empty_buffers, rx_empty_buffers) = crossbeam_channel::unbounded();
+        for _ in 0..4 {
+            tx_empty_buffers
+                .send(Box::new(CpuBuffer::new(8192)) as Box<dyn Buffer + Send>)
+                .unwrap();
+        }
+
+        let mut plot = Plot::new(&path, false, false).unwrap();
+        let scoop = 7;
+        plot.prepare(scoop).unwrap();
+        let result = read_plot(
+            &mut ring,
+            &mut plot,
+            scoop,
+            3,
+            &rx_empty_buffers,
+            &tx_empty_buffers,
+            &|| false,
+            &mut |_, _, _, _| panic!("nothing was read"),
+        );
+        assert!(result.is_err());
+        assert_eq!(rx_empty_buffers.len(), 4);
+
+        // the blocking reads continue where the ring stopped
+        let mut read = Vec::new();
+        let mut bs = vec![0u8; 8192];
+        loop {
+            let (len, start_nonce, finished) = plot.read(&mut bs, scoop).unwrap();
+            assert_eq!(start_nonce, read.len() as u64 / SCOOP_SIZE);
+            read.extend_from_slice(&bs[..len]);
+            if finished {
+                break;
+            }
+        }
+        let scoop_size = (nonces * SCOOP_SIZE) as usize;
+        let offset = scoop as usize * scoop_size;
+        assert!(read == data[offset..offset + scoop_size].to_vec());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,31 +1,112 @@
+use crate::account::Account;
 use crate::com::api::{FetchError, MiningInfoResponse};
 use crate::com::client::{Client, ProxyDetails, SubmissionParameters};
+use crate::com::upstreams::Upstreams;
+use crate::config::{AccountUpstream, SecretPhrase};
 use crate::future::prio_retry::PrioRetry;
+use crate::history;
+use crate::metrics;
 use futures::future::Future;
 use futures::stream::Stream;
 use futures::sync::mpsc;
-use std::collections::HashMap;
-use std::time::Duration;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::u64;
+use stopwatch::Stopwatch;
 use tokio;
 use tokio::runtime::TaskExecutor;
 use url::Url;
-use stopwatch::Stopwatch;
+
+// submissions kept for the status server
+const RECENT_SUBMISSIONS: usize = 100;
 
 #[derive(Clone)]
 pub struct RequestHandler {
-    client: Client,
+    upstreams: Arc<Upstreams>,
+    account_clients: Arc<HashMap<u64, Client>>,
     tx_submit_data: mpsc::UnboundedSender<SubmissionParameters>,
+    recent_submissions: Arc<Mutex<VecDeque<SubmissionRecord>>>,
+    executor: TaskExecutor,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubmissionOutcome {
+    Accepted,
+    DeadlineMismatch,
+    NotAccepted,
+    PoolBusy,
+    Failed,
+}
+
+impl SubmissionOutcome {
+    fn name(self) -> &'static str {
+        match self {
+            SubmissionOutcome::Accepted => "accepted",
+            SubmissionOutcome::DeadlineMismatch => "deadline_mismatch",
+            SubmissionOutcome::NotAccepted => "not_accepted",
+            SubmissionOutcome::PoolBusy => "pool_busy",
+            SubmissionOutcome::Failed => "failed",
+        }
+    }
+}
+
+/// A submission along with the answer of its upstream.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SubmissionRecord {
+    // unix time in seconds
+    pub time: u64,
+    pub height: u64,
+    pub account_id: u64,
+    pub account: String,
+    pub nonce: u64,
+    pub deadline: u64,
+    pub upstream: String,
+    pub outcome: SubmissionOutcome,
+    pub deadline_pool: Option<u64>,
+    pub message: Option<String>,
+    pub latency_ms: i64,
+}
+
+impl SubmissionRecord {
+    fn new(
+        params: &SubmissionParameters,
+        upstream: &Url,
+        outcome: SubmissionOutcome,
+        latency_ms: i64,
+    ) -> SubmissionRecord {
+        SubmissionRecord {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            height: params.height,
+            account_id: params.account_id,
+            account: Account(params.account_id).to_string(),
+            nonce: params.nonce,
+            deadline: params.deadline,
+            upstream: upstream.to_string(),
+            outcome,
+            deadline_pool: None,
+            message: None,
+            latency_ms,
+        }
+    }
 }
 
 impl RequestHandler {
+    /// Requests go to the first of `base_uris` that is reachable, except for submissions of
+    /// accounts that have their own upstream.
     pub fn new(
-        base_uri: Url,
-        secret_phrases: HashMap<u64, String>,
+        base_uris: Vec<Url>,
+        secret_phrases: HashMap<u64, SecretPhrase>,
+        secret_phrase_in_query: bool,
         timeout: u64,
         total_size_gb: usize,
         send_proxy_details: bool,
         additional_headers: HashMap<String, String>,
+        account_id_to_upstream: HashMap<u64, AccountUpstream>,
         executor: TaskExecutor,
     ) -> RequestHandler {
         // TODO
@@ -35,103 +116,205 @@ impl RequestHandler {
             ProxyDetails::Disabled
         };
 
-        let client = Client::new(
-            base_uri,
-            secret_phrases,
-            timeout,
-            total_size_gb,
-            proxy_details,
-            additional_headers,
-        );
+        let clients = base_uris
+            .into_iter()
+            .map(|base_uri| {
+                Client::new(
+                    base_uri,
+                    secret_phrases.clone(),
+                    secret_phrase_in_query,
+                    timeout,
+                    total_size_gb,
+                    proxy_details.clone(),
+                    additional_headers.clone(),
+                )
+            })
+            .collect();
+        let upstreams = Arc::new(Upstreams::new(clients));
+
+        let account_clients: HashMap<u64, Client> = account_id_to_upstream
+            .into_iter()
+            .map(|(account_id, upstream)| {
+                info!(
+                    "account routed: account={}, upstream={}",
+                    Account(account_id),
+                    upstream.url
+                );
+                let mut secret_phrase = HashMap::new();
+                if let Some(phrase) = upstream
+                    .secret_phrase
+                    .or_else(|| secret_phrases.get(&account_id).cloned())
+                {
+                    secret_phrase.insert(account_id, phrase);
+                }
+                let client = Client::new(
+                    upstream.url,
+                    secret_phrase,
+                    secret_phrase_in_query,
+                    timeout,
+                    total_size_gb,
+                    proxy_details.clone(),
+                    upstream.additional_headers,
+                );
+                (account_id, client)
+            })
+            .collect();
+        let account_clients = Arc::new(account_clients);
 
         let (tx_submit_data, rx_submit_nonce_data) = mpsc::unbounded();
+        let recent_submissions = Arc::new(Mutex::new(VecDeque::new()));
         RequestHandler::handle_submissions(
-            client.clone(),
+            upstreams.clone(),
+            account_clients.clone(),
             rx_submit_nonce_data,
             tx_submit_data.clone(),
-            executor,
+            recent_submissions.clone(),
+            executor.clone(),
         );
 
         RequestHandler {
-            client,
+            upstreams,
+            account_clients,
             tx_submit_data,
+            recent_submissions,
+            executor,
         }
     }
 
     fn handle_submissions(
-        client: Client,
+        upstreams: Arc<Upstreams>,
+        account_clients: Arc<HashMap<u64, Client>>,
         rx: mpsc::UnboundedReceiver<SubmissionParameters>,
         tx_submit_data: mpsc::UnboundedSender<SubmissionParameters>,
+        recent_submissions: Arc<Mutex<VecDeque<SubmissionRecord>>>,
         executor: TaskExecutor,
     ) {
-        let stream = PrioRetry::new(rx, Duration::from_secs(3))
+        // One retry slot per account, so that a better deadline of one account doesn't
+        // replace the pending retry of another. The account id alone is enough to keep
+        // upstreams apart as well: an account is only ever sent to one client of a handler,
+        // either its own or the active upstream. The active upstream is looked up when a
+        // retry is sent, so a retry pending during failover goes to the upstream taking over.
+        let stream = PrioRetry::new(rx, Duration::from_secs(3), |params| params.account_id)
             .and_then(move |submission_params| {
                 let tx_submit_data = tx_submit_data.clone();
+                let upstreams = upstreams.clone();
+                let recent_submissions = recent_submissions.clone();
+                // accounts with their own upstream don't take part in failover
+                let (active, client) = match account_clients.get(&submission_params.account_id) {
+                    Some(client) => (None, client),
+                    None => {
+                        let (active, client) = upstreams.active_client();
+                        (Some(active), client)
+                    }
+                };
+                let upstream = client.base_uri().clone();
                 let mut sw = Stopwatch::new();
                 sw.start();
-                client
-                    .clone()
-                    .submit_nonce(&submission_params)
-                    .then(move |res| {
-                        sw.stop();
+                client.submit_nonce(&submission_params).then(move |res| {
+                    sw.stop();
+                    let record = |outcome| {
+                        SubmissionRecord::new(
+                            &submission_params,
+                            &upstream,
+                            outcome,
+                            sw.elapsed_ms(),
+                        )
+                    };
+                    // errors reported by the pool don't count towards failover
+                    if let Some(active) = active {
                         match res {
-                            Ok(res) => {
-                                if submission_params.deadline != res.deadline {
-                                    log_deadline_mismatch(
-                                        submission_params.height,
-                                        submission_params.account_id,
-                                        submission_params.nonce,
-                                        submission_params.deadline,
-                                        res.deadline,
-                                    );
-                                } else {
-                                    log_submission_accepted(
-                                        submission_params.account_id,
-                                        submission_params.nonce,
-                                        submission_params.deadline,
-                                        sw.elapsed_ms()
-                                    );
-                                }
-                            }
-                            Err(FetchError::Pool(e)) => {
-                                // Very intuitive, if some pools send an empty message they are
-                                // experiencing too much load expect the submission to be resent later.
-                                if e.message.is_empty() || e.message == "limit exceeded" {
-                                    log_pool_busy(
-                                        submission_params.account_id,
-                                        submission_params.nonce,
-                                        submission_params.deadline,
-                                    );
-                                    let res = tx_submit_data.unbounded_send(submission_params);
-                                    if let Err(e) = res {
-                                        error!("can't send submission params: {}", e);
-                                    }
-                                } else {
-                                    log_submission_not_accepted(
-                                        submission_params.height,
-                                        submission_params.account_id,
-                                        submission_params.nonce,
-                                        submission_params.deadline,
-                                        e.code,
-                                        &e.message,
-                                    );
-                                }
+                            Err(FetchError::Http(_)) => upstreams.failed(active),
+                            _ => upstreams.answered(active),
+                        }
+                    }
+                    let record = match res {
+                        Ok(res) => {
+                            let outcome = if submission_params.deadline != res.deadline {
+                                log_deadline_mismatch(
+                                    submission_params.height,
+                                    submission_params.account_id,
+                                    submission_params.nonce,
+                                    submission_params.deadline,
+                                    res.deadline,
+                                );
+                                SubmissionOutcome::DeadlineMismatch
+                            } else {
+                                log_submission_accepted(
+                                    submission_params.height,
+                                    submission_params.account_id,
+                                    submission_params.nonce,
+                                    submission_params.deadline,
+                                    sw.elapsed_ms(),
+                                );
+                                SubmissionOutcome::Accepted
+                            };
+                            SubmissionRecord {
+                                deadline_pool: Some(res.deadline),
+                                ..record(outcome)
                             }
-                            Err(FetchError::Http(x)) => {
-                                log_submission_failed(
+                        }
+                        Err(FetchError::Pool(e)) => {
+                            // Very intuitive, if some pools send an empty message they are
+                            // experiencing too much load expect the submission to be resent later.
+                            if e.message.is_empty() || e.message == "limit exceeded" {
+                                log_pool_busy(
+                                    submission_params.height,
                                     submission_params.account_id,
                                     submission_params.nonce,
                                     submission_params.deadline,
-                                    &x.to_string(),
                                 );
+                                let record = record(SubmissionOutcome::PoolBusy);
                                 let res = tx_submit_data.unbounded_send(submission_params);
                                 if let Err(e) = res {
                                     error!("can't send submission params: {}", e);
                                 }
+                                record
+                            } else {
+                                log_submission_not_accepted(
+                                    submission_params.height,
+                                    submission_params.account_id,
+                                    submission_params.nonce,
+                                    submission_params.deadline,
+                                    e.code,
+                                    &e.message,
+                                );
+                                SubmissionRecord {
+                                    message: Some(e.message),
+                                    ..record(SubmissionOutcome::NotAccepted)
+                                }
                             }
-                        };
-                        Ok(())
-                    })
+                        }
+                        Err(FetchError::Http(x)) => {
+                            log_submission_failed(
+                                submission_params.height,
+                                submission_params.account_id,
+                                submission_params.nonce,
+                                submission_params.deadline,
+                                &x.to_string(),
+                            );
+                            let record = SubmissionRecord {
+                                message: Some(x.to_string()),
+                                ..record(SubmissionOutcome::Failed)
+                            };
+                            let res = tx_submit_data.unbounded_send(submission_params);
+                            if let Err(e) = res {
+                                error!("can't send submission params: {}", e);
+                            }
+                            record
+                        }
+                    };
+                    metrics::SUBMISSIONS.inc(&[
+                        ("account", &record.account_id.to_string()),
+                        ("outcome", record.outcome.name()),
+                    ]);
+                    history::record_submission(&record);
+                    let mut recent_submissions = recent_submissions.lock().unwrap();
+                    if recent_submissions.len() == RECENT_SUBMISSIONS {
+                        recent_submissions.pop_front();
+                    }
+                    recent_submissions.push_back(record);
+                    Ok(())
+                })
             })
             .for_each(|_| Ok(()))
             .map_err(|e| error!("can't handle submission params: {:?}", e));
@@ -139,7 +322,51 @@ impl RequestHandler {
     }
 
     pub fn get_mining_info(&self) -> impl Future<Item = MiningInfoResponse, Error = FetchError> {
-        self.client.get_mining_info()
+        // preferred upstreams take over again as soon as they answer
+        for i in self.upstreams.due_for_recheck() {
+            let upstreams = self.upstreams.clone();
+            let health_check = self.upstreams.clients()[i]
+                .get_mining_info()
+                .then(move |res| {
+                    if res.is_ok() {
+                        upstreams.recovered(i);
+                    }
+                    Ok(())
+                });
+            self.executor.spawn(health_check);
+        }
+
+        let upstreams = self.upstreams.clone();
+        let (active, client) = self.upstreams.active_client();
+        client.get_mining_info().then(move |res| {
+            match res {
+                Err(FetchError::Http(_)) => upstreams.failed(active),
+                _ => upstreams.answered(active),
+            }
+            res
+        })
+    }
+
+    /// The upstream requests currently go to.
+    pub fn active_upstream(&self) -> Url {
+        self.upstreams.active_client().1.base_uri().clone()
+    }
+
+    /// The latest submissions and how they were answered, newest first.
+    pub fn recent_submissions(&self) -> Vec<SubmissionRecord> {
+        let recent_submissions = self.recent_submissions.lock().unwrap();
+        recent_submissions.iter().rev().cloned().collect()
+    }
+
+    pub fn set_total_size_gb(&self, total_size_gb: usize) {
+        for client in self
+            .upstreams
+            .clients()
+            .iter()
+            .chain(self.account_clients.values())
+        {
+            client.set_total_size_gb(total_size_gb);
+        }
     }
 
     pub fn submit_nonce(
@@ -175,18 +402,21 @@ fn log_deadline_mismatch(
     deadline_pool: u64,
 ) {
     error!(
+        height = height, account = account_id, nonce = nonce, deadline = deadline,
+        deadline_pool = deadline_pool;
         "submit: deadlines mismatch, height={}, account={}, nonce={}, \
          deadline_miner={}, deadline_pool={}",
-        height, account_id, nonce, deadline, deadline_pool
+        height, Account(account_id), nonce, deadline, deadline_pool
     );
 }
 
-fn log_submission_failed(account_id: u64, nonce: u64, deadline: u64, err: &str) {
+fn log_submission_failed(height: u64, account_id: u64, nonce: u64, deadline: u64, err: &str) {
     warn!(
+        height = height, account = account_id, nonce = nonce, deadline = deadline;
         "{: <80}",
         format!(
             "submission failed, retrying: account={}, nonce={}, deadline={}, description={}",
-            account_id, nonce, deadline, err
+            Account(account_id), nonce, deadline, err
         )
     );
 }
@@ -200,49 +430,170 @@ fn log_submission_not_accepted(
     msg: &str,
 ) {
     error!(
+        height = height, account = account_id, nonce = nonce, deadline = deadline,
+        code = err_code;
         "submission not accepted: height={}, account={}, nonce={}, \
          deadline={}\n\tcode: {}\n\tmessage: {}",
-        height, account_id, nonce, deadline, err_code, msg,
+        height, Account(account_id), nonce, deadline, err_code, msg,
     );
 }
 
-fn log_submission_accepted(account_id: u64, nonce: u64, deadline: u64, latency: i64) {
+fn log_submission_accepted(height: u64, account_id: u64, nonce: u64, deadline: u64, latency: i64) {
     info!(
+        height = height, account = account_id, nonce = nonce, deadline = deadline,
+        latency_ms = latency;
         "deadline accepted: account={}, nonce={}, deadline={}, latency={}ms",
-        account_id, nonce, deadline, latency
+        Account(account_id), nonce, deadline, latency
     );
 }
 
-fn log_pool_busy(account_id: u64, nonce: u64, deadline: u64) {
+fn log_pool_busy(height: u64, account_id: u64, nonce: u64, deadline: u64) {
     info!(
+        height = height, account = account_id, nonce = nonce, deadline = deadline;
         "pool busy, retrying: account={}, nonce={}, deadline={}",
-        account_id, nonce, deadline
+        Account(account_id), nonce, deadline
     );
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::com::mock::{MockReply, MockServer};
+    use std::thread;
+    use std::time::Instant;
     use tokio;
 
-    static BASE_URL: &str = "http://94.130.178.37:31000";
+    fn new_request_handler(
+        base_uris: Vec<Url>,
+        account_id_to_upstream: HashMap<u64, AccountUpstream>,
+        executor: TaskExecutor,
+    ) -> RequestHandler {
+        RequestHandler::new(
+            base_uris,
+            HashMap::new(),
+            false,
+            1000,
+            12,
+            true,
+            HashMap::new(),
+            account_id_to_upstream,
+            executor,
+        )
+    }
 
     #[test]
     fn test_submit_nonce() {
         let rt = tokio::runtime::Runtime::new().expect("can't create runtime");
+        let pool = MockServer::start();
+        let wallet = MockServer::start();
+
+        let mut account_id_to_upstream = HashMap::new();
+        account_id_to_upstream.insert(
+            2,
+            AccountUpstream {
+                url: wallet.url(),
+                additional_headers: HashMap::new(),
+                secret_phrase: Some(SecretPhrase::new("secret".to_owned())),
+            },
+        );
+        let request_handler =
+            new_request_handler(vec![pool.url()], account_id_to_upstream, rt.executor());
 
-        let request_handler = RequestHandler::new(
-            BASE_URL.parse().unwrap(),
+        // a busy pool gets the submission again
+        pool.script_replies(vec![MockReply::Raw(200, "limit exceeded".to_owned())]);
+        request_handler.submit_nonce(1337, 12, 1, 0, 7123, 1193, [0; 32]);
+        request_handler.submit_nonce(2, 13, 1, 0, 7123, 1193, [0; 32]);
+
+        let submissions = pool.wait_for_submissions(2, Duration::from_secs(10));
+        assert_eq!(submissions.len(), 2);
+        assert!(submissions
+            .iter()
+            .all(|s| s.account_id == 1337 && s.nonce == 12));
+
+        let submissions = wallet.submissions();
+        assert_eq!(submissions.len(), 1);
+        assert_eq!(submissions[0].account_id, 2);
+        assert_eq!(submissions[0].secret_phrase, Some("secret".to_owned()));
+
+        // the pool's answers are kept for the status server, newest first
+        let timeout = Instant::now() + Duration::from_secs(10);
+        while request_handler.recent_submissions().len() < 3 && Instant::now() < timeout {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let outcomes: Vec<SubmissionOutcome> = request_handler
+            .recent_submissions()
+            .iter()
+            .filter(|record| record.account_id == 1337)
+            .map(|record| record.outcome)
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![SubmissionOutcome::Accepted, SubmissionOutcome::PoolBusy]
+        );
+
+        rt.shutdown_now().wait().unwrap();
+    }
+
+    #[test]
+    fn test_failover() {
+        let mut rt = tokio::runtime::Runtime::new().expect("can't create runtime");
+        let pool = MockServer::start();
+        let request_handler = new_request_handler(
+            vec!["http://127.0.0.1:1".parse().unwrap(), pool.url()],
             HashMap::new(),
-            3,
-            12,
-            true,
+            rt.executor(),
+        );
+
+        // the unreachable upstream is given up after a few failed requests in a row
+        let mut failures = 0;
+        while request_handler.active_upstream() != pool.url() {
+            assert!(failures < 10, "no failover");
+            let mining_info = request_handler.get_mining_info();
+            assert!(rt.block_on(mining_info).is_err());
+            failures += 1;
+        }
+        assert!(failures > 1);
+
+        request_handler.submit_nonce(1337, 12, 1, 0, 7123, 1193, [0; 32]);
+        let submissions = pool.wait_for_submissions(1, Duration::from_secs(10));
+        assert_eq!(submissions.len(), 1);
+        assert_eq!(request_handler.active_upstream(), pool.url());
+
+        rt.shutdown_now().wait().unwrap();
+    }
+
+    #[test]
+    fn test_retry_after_failover() {
+        let mut rt = tokio::runtime::Runtime::new().expect("can't create runtime");
+        let pool = MockServer::start();
+        let request_handler = new_request_handler(
+            vec!["http://127.0.0.1:1".parse().unwrap(), pool.url()],
             HashMap::new(),
             rt.executor(),
         );
 
-        request_handler.submit_nonce(1337, 12, 111, 0, 7123, 1193, [0; 32]);
+        // the submission fails and waits for its retry while the upstream is switched
+        request_handler.submit_nonce(1337, 12, 1, 0, 7123, 1193, [0; 32]);
+        let timeout = Instant::now() + Duration::from_secs(10);
+        while request_handler.recent_submissions().is_empty() && Instant::now() < timeout {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            request_handler.recent_submissions()[0].outcome,
+            SubmissionOutcome::Failed
+        );
+        while request_handler.active_upstream() != pool.url() {
+            let mining_info = request_handler.get_mining_info();
+            assert!(rt.block_on(mining_info).is_err());
+        }
+
+        let submissions = pool.wait_for_submissions(1, Duration::from_secs(10));
+        assert_eq!(submissions.len(), 1);
+        assert_eq!(
+            (submissions[0].account_id, submissions[0].nonce),
+            (1337, 12)
+        );
 
-        rt.shutdown_on_idle();
+        rt.shutdown_now().wait().unwrap();
     }
 }
@@ -1,5 +1,3 @@
-use std::slice::from_raw_parts;
-
 const A_INIT: [u32; 12] = [
     0x52F84552, 0xE54B7999, 0x2D8EE3EC, 0xB9645191, 0xE0078B86, 0xBB7C44C9, 0xD2B5C1CA, 0xB0D2EB8C,
     0x14CE5A45, 0x22AF50DC, 0xEFFDBC6B, 0xEB21B74A,
@@ -21,9 +19,12 @@ pub fn shabal256_deadline_fast(data: &[u8], gensig: &[u8; 32]) -> u64 {
     let mut c = C_INIT;
     let mut w_high = 0u32;
     let mut w_low = 1u32;
-    let data_ptr = data.as_ptr() as *const u32;
-    let data = unsafe { from_raw_parts(data_ptr, data.len() / 4) };
-    let gensig = unsafe { std::mem::transmute::<&[u8; 32], &[u32; 8]>(&gensig) };
+    let mut words = [0u32; 16];
+    read_words(&data[..64], &mut words);
+    let data = &words;
+    let mut gensig_words = [0u32; 8];
+    read_words(gensig, &mut gensig_words);
+    let gensig = &gensig_words;
     let mut term = [0u32; 8];
     term[0] = 0x80;
 
@@ -42,8 +43,7 @@ pub fn shabal256_deadline_fast(data: &[u8], gensig: &[u8; 32]) -> u64 {
         xor_w(&mut a, w_low, w_high);
         apply_p_dl(&mut a, &mut b, &c, &data[8..], &term);
     }
-    let b = unsafe { std::mem::transmute::<&[u32; 16], &[u64; 8]>(&b) };
-    b[4]
+    u64::from(b[8]) | u64::from(b[9]) << 32
 }
 
 pub fn shabal256_hash_fast(data: &[u8], term: &[u32; 16]) -> [u8; 32] {
@@ -52,20 +52,16 @@ pub fn shabal256_hash_fast(data: &[u8], term: &[u32; 16]) -> [u8; 32] {
     let mut c = C_INIT;
     let mut w_high = 0u32;
     let mut w_low = 1u32;
-    let mut num = data.len() >> 6;
-    let mut ptr = 0;
-    let data_ptr = data.as_ptr() as *const u32;
-    let data = unsafe { from_raw_parts(data_ptr, data.len() / 4) };
+    let mut words = [0u32; 16];
 
-    while num > 0 {
-        input_block_add(&mut b, &data[ptr..]);
+    for block in data.chunks_exact(64) {
+        read_words(block, &mut words);
+        input_block_add(&mut b, &words);
         xor_w(&mut a, w_low, w_high);
-        apply_p(&mut a, &mut b, &c, &data[ptr..]);
-        input_block_sub(&mut c, &data[ptr..]);
+        apply_p(&mut a, &mut b, &c, &words);
+        input_block_sub(&mut c, &words);
         swap_bc(&mut b, &mut c);
         incr_w(&mut w_low, &mut w_high);
-        ptr = ptr.wrapping_add(16);
-        num = num.wrapping_sub(1);
     }
     input_block_add(&mut b, term);
     xor_w(&mut a, w_low, w_high);
@@ -78,6 +74,14 @@ pub fn shabal256_hash_fast(data: &[u8], term: &[u32; 16]) -> [u8; 32] {
     unsafe { *(b[8..16].as_ptr() as *const [u8; 32]) }
 }
 
+// input is read as little-endian words, byte slices don't have to be aligned for u32
+#[inline(always)]
+fn read_words(bytes: &[u8], words: &mut [u32]) {
+    for (word, bytes) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+}
+
 #[inline(always)]
 fn input_block_add(b: &mut [u32; 16], data: &[u32]) {
     for (element, data) in b.iter_mut().zip(data.iter()) {
@@ -509,4 +513,24 @@ mod test {
         };
         assert_eq!(hash_b, TEST_B_RESULT);
     }
+
+    #[test]
+    fn test_misaligned_input() {
+        let bytes: Vec<u8> = (0..129u32).map(|i| (i * 7) as u8).collect();
+        let gensig = [3u8; 32];
+        let mut term = [0u32; 16];
+        term[0] = 0x80;
+        let mut aligned = [0u32; 32];
+        read_words(&bytes[1..], &mut aligned);
+        let aligned: Vec<u8> = aligned.iter().flat_map(|w| w.to_le_bytes()).collect();
+
+        assert_eq!(
+            shabal256_hash_fast(&bytes[1..], &term),
+            shabal256_hash_fast(&aligned, &term)
+        );
+        assert_eq!(
+            shabal256_deadline_fast(&bytes[1..65], &gensig),
+            shabal256_deadline_fast(&aligned[..64], &gensig)
+        );
+    }
 }
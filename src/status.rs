@@ -0,0 +1,129 @@
+use crate::metrics;
+use crate::miner::{RoundStatus, ScannedPlots, State};
+use crate::reader::{DriveProgress, Drives};
+use crate::requests::RequestHandler;
+use futures::future;
+use futures::Future;
+use hyper::service::service_fn;
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::TaskExecutor;
+
+/// Serves what the miner is doing as JSON, so that dashboards don't have to scrape the logs:
+/// `/round`, `/drives`, `/submissions` and `/plots`, along with `/metrics` for Prometheus.
+pub struct Status {
+    chains: Vec<(Arc<Mutex<State>>, RequestHandler)>,
+    drives: Drives,
+    scanned_plots: ScannedPlots,
+}
+
+impl Status {
+    pub fn new(
+        chains: Vec<(Arc<Mutex<State>>, RequestHandler)>,
+        drives: Drives,
+        scanned_plots: ScannedPlots,
+    ) -> Status {
+        Status {
+            chains,
+            drives,
+            scanned_plots,
+        }
+    }
+
+    /// Starts serving requests on `addr`.
+    pub fn serve(status: Arc<Status>, addr: SocketAddr, executor: &TaskExecutor) {
+        let builder = match Server::try_bind(&addr) {
+            Ok(builder) => builder,
+            Err(e) => {
+                error!("status: can't listen on {}: {}", addr, e);
+                return;
+            }
+        };
+        info!("status: listening on {}", addr);
+        let server = builder
+            .serve(move || {
+                let status = status.clone();
+                service_fn(move |req| status.handle(req))
+            })
+            .map_err(|e| error!("status: server failed: {}", e));
+        executor.spawn(server);
+    }
+
+    fn handle(
+        &self,
+        req: Request<Body>,
+    ) -> impl Future<Item = Response<Body>, Error = hyper::Error> {
+        let (status, content_type, body) = self.respond(req.method(), req.uri().path());
+        future::ok(
+            Response::builder()
+                .status(status)
+                .header("Content-Type", content_type)
+                .body(Body::from(body))
+                .unwrap(),
+        )
+    }
+
+    fn respond(&self, method: &Method, path: &str) -> (StatusCode, &'static str, String) {
+        if method != Method::GET {
+            return error(StatusCode::METHOD_NOT_ALLOWED, "only GET is supported");
+        }
+        match path.trim_end_matches('/') {
+            "/metrics" => (StatusCode::OK, metrics::CONTENT_TYPE, metrics::render()),
+            "/round" => json(&self.round()),
+            "/drives" => json(&self.drives()),
+            "/submissions" => json(&self.submissions()),
+            "/plots" => json(&*self.scanned_plots.get()),
+            _ => error(StatusCode::NOT_FOUND, "unknown path"),
+        }
+    }
+
+    /// The current round of every chain.
+    pub fn round(&self) -> Vec<RoundStatus> {
+        self.chains
+            .iter()
+            .enumerate()
+            .map(|(i, (state, request_handler))| {
+                let upstream = request_handler.active_upstream();
+                state.lock().unwrap().status(i, &upstream)
+            })
+            .collect()
+    }
+
+    /// The progress of every drive in the latest round of each chain.
+    pub fn drives(&self) -> Vec<DriveProgress> {
+        self.drives.progress()
+    }
+
+    fn submissions(&self) -> serde_json::Value {
+        let submissions: Vec<serde_json::Value> = self
+            .chains
+            .iter()
+            .enumerate()
+            .map(|(i, (_, request_handler))| {
+                serde_json::json!({
+                    "chain": i,
+                    "submissions": request_handler.recent_submissions(),
+                })
+            })
+            .collect();
+        serde_json::Value::from(submissions)
+    }
+}
+
+fn json<T: Serialize>(value: &T) -> (StatusCode, &'static str, String) {
+    (
+        StatusCode::OK,
+        "application/json",
+        serde_json::to_string(value).unwrap(),
+    )
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, &'static str, String) {
+    (
+        status,
+        "application/json",
+        serde_json::json!({ "error": { "message": message } }).to_string(),
+    )
+}
@@ -25,81 +25,95 @@ pub fn new_thread_pool(num_threads: usize, thread_pinning: bool) -> rayon::Threa
 
 cfg_if! {
     if #[cfg(unix)] {
-        use std::process::Command;
-
-        pub fn get_device_id(path: &str) -> String {
-            let output = Command::new("stat")
-                .arg(path)
-                .arg("-c %D")
-                .output()
-                .expect("failed to execute 'stat -c %D'");
-            String::from_utf8(output.stdout).expect("not utf8").trim_end().to_owned()
-        }
+        use std::fs;
+        use std::io;
+        use std::os::unix::fs::MetadataExt;
 
-        // On unix, get the device id from 'df' command
-        fn get_device_id_unix(path: &str) -> String {
-            let output = Command::new("df")
-                 .arg(path)
-                 .output()
-                 .expect("failed to execute 'df'");
-             let source = String::from_utf8(output.stdout).expect("not utf8");
-             source.split('\n').collect::<Vec<&str>>()[1].split(' ').collect::<Vec<&str>>()[0].to_string()
-         }
+        pub fn get_device_id(path: &str) -> io::Result<String> {
+            Ok(format!("{:x}", fs::metadata(path)?.dev()))
+        }
 
         // On macos, use df and 'diskutil info <device>' to get the Device Block Size line
         // and extract the size
-        fn get_sector_size_macos(path: &str) -> u64 {
-            let source = get_device_id_unix(path);
-            let output = Command::new("diskutil")
-                .arg("info")
-                .arg(source)
-                .output()
-                .expect("failed to execute 'diskutil info'");
-            let source = String::from_utf8(output.stdout).expect("not utf8");
-            let mut sector_size: u64 = 0;
-            for line in source.split('\n').collect::<Vec<&str>>() {
+        #[cfg(target_os = "macos")]
+        pub fn get_sector_size(path: &str) -> io::Result<u64> {
+            use std::process::Command;
+
+            let output = Command::new("df").arg(path).output()?;
+            let source = String::from_utf8_lossy(&output.stdout);
+            let source = source
+                .lines()
+                .nth(1)
+                .and_then(|line| line.split_whitespace().next())
+                .ok_or_else(|| {
+                    let msg = format!("no device in df output for {}", path);
+                    io::Error::new(io::ErrorKind::Other, msg)
+                })?;
+
+            let output = Command::new("diskutil").arg("info").arg(source).output()?;
+            let source = String::from_utf8_lossy(&output.stdout);
+            for line in source.lines() {
                 if line.trim().starts_with("Device Block Size") {
                     // e.g. in reverse: "Bytes 512 Size Block Device"
-                    let source = line.rsplit(' ').collect::<Vec<&str>>()[1];
+                    if let Some(Ok(sector_size)) = line.rsplit(' ').nth(1).map(str::parse) {
+                        return Ok(sector_size);
+                    }
+                }
+            }
+            warn!("unable to determine disk physical sector size from diskutil info, using 4096");
+            Ok(4096)
+        }
 
-                    sector_size = source.parse::<u64>().unwrap();
+        // Reads an attribute from the queue directory of the block device behind a device
+        // number. Partitions don't have a queue directory, theirs is the one of their disk.
+        #[cfg(target_os = "linux")]
+        pub fn get_block_queue_attribute(dev: u64, attribute: &str) -> io::Result<String> {
+            let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+            let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+            let device = fs::canonicalize(format!("/sys/dev/block/{}:{}", major, minor))?;
+            let mut attribute_path = device.join("queue").join(attribute);
+            if !attribute_path.exists() {
+                if let Some(disk) = device.parent() {
+                    attribute_path = disk.join("queue").join(attribute);
                 }
             }
-            if sector_size == 0 {
-                sector_size = 4096;
-                warn!("Abort: Unable to determine disk physical sector size from diskutil info. Using default 4096")
+            Ok(fs::read_to_string(attribute_path)?.trim().to_owned())
+        }
+
+        // On linux, the sector size is taken from sysfs
+        #[cfg(target_os = "linux")]
+        pub fn get_sector_size(path: &str) -> io::Result<u64> {
+            let dev = fs::metadata(path)?.dev();
+            match get_block_queue_attribute(dev, "physical_block_size")
+                .ok()
+                .and_then(|sector_size| sector_size.parse().ok())
+            {
+                Some(sector_size) => Ok(sector_size),
+                None => {
+                    // e.g. network file systems, tmpfs or btrfs subvolumes
+                    warn!("no block device for {}, defaulting to sector size 4096", path);
+                    Ok(4096)
+                }
             }
-            sector_size
         }
 
-        // On unix, use df and lsblk to extract the device sector size
-        fn get_sector_size_unix(path: &str) -> u64 {
-            let source = get_device_id_unix(path);
-            let output = Command::new("lsblk")
-                .arg(source)
-                .arg("-o")
-                .arg("PHY-SeC")
-                .output()
-                .map(|output| output.stdout)
-                .unwrap_or_default();
-
-            let sector_size = String::from_utf8(output).expect("not utf8");
-            let sector_size = sector_size.split('\n').collect::<Vec<&str>>().get(1).unwrap_or_else(|| {
-                warn!("failed to determine sector size, defaulting to 4096.");
-                &"4096"
-            }).trim();
-
-            sector_size.parse::<u64>().unwrap()
+        #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+        pub fn get_sector_size(_path: &str) -> io::Result<u64> {
+            Ok(4096)
         }
 
-        pub fn get_sector_size(path: &str) -> u64 {
-            if cfg!(target_os = "android") {
-                4096
-            } else if cfg!(target_os = "macos") {
-                get_sector_size_macos(path)
-            } else {
-                get_sector_size_unix(path)
-            }
+        // Unknown devices are treated as spinning disks, i.e. read sequentially
+        #[cfg(target_os = "linux")]
+        pub fn is_rotational(path: &str) -> io::Result<bool> {
+            let dev = fs::metadata(path)?.dev();
+            Ok(get_block_queue_attribute(dev, "rotational")
+                .map(|rotational| rotational != "0")
+                .unwrap_or(true))
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        pub fn is_rotational(_path: &str) -> io::Result<bool> {
+            Ok(true)
         }
     } else {
         use winapi;
@@ -109,9 +123,10 @@ cfg_if! {
         use std::ffi::OsStr;
         use std::iter::once;
         use std::ffi::CString;
+        use std::io;
         use std::path::Path;
 
-        pub fn get_device_id(path: &str) -> String {
+        pub fn get_device_id(path: &str) -> io::Result<String> {
             let path_encoded: Vec<u16> = OsStr::new(path).encode_wide().chain(once(0)).collect();
             let mut volume_encoded: Vec<u16> = OsStr::new(path)
                 .encode_wide()
@@ -125,17 +140,18 @@ cfg_if! {
                     path.chars().count() as u32
                 )
             } == 0  {
-                panic!("get volume path name");
+                return Err(io::Error::last_os_error());
             };
             let res = String::from_utf16_lossy(&volume_encoded);
             let v: Vec<&str> = res.split('\u{00}').collect();
-            String::from(v[0])
+            Ok(String::from(v[0]))
         }
 
-        pub fn get_sector_size(path: &str) -> u64 {
+        pub fn get_sector_size(path: &str) -> io::Result<u64> {
             let path_encoded = Path::new(path);
             let parent_path = path_encoded.parent().unwrap().to_str().unwrap();
-            let parent_path_encoded = CString::new(parent_path).unwrap();
+            let parent_path_encoded = CString::new(parent_path)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
             let mut sectors_per_cluster  = 0u32;
             let mut bytes_per_sector  = 0u32;
             let mut number_of_free_cluster  = 0u32;
@@ -149,9 +165,13 @@ cfg_if! {
                     &mut total_number_of_cluster
                 )
             } == 0  {
-                panic!("get sector size, filename={}",path);
+                return Err(io::Error::last_os_error());
             };
-            u64::from(bytes_per_sector)
+            Ok(u64::from(bytes_per_sector))
+        }
+
+        pub fn is_rotational(_path: &str) -> io::Result<bool> {
+            Ok(true)
         }
 
         pub fn set_thread_ideal_processor(id: usize){
@@ -170,12 +190,16 @@ cfg_if! {
 #[cfg(test)]
 mod test {
     use super::*;
-    //use std::env;
 
     #[test]
     fn test_get_device_id() {
+        assert_ne!("", get_device_id("Cargo.toml").unwrap());
+        assert_eq!(
+            get_device_id("Cargo.toml").unwrap(),
+            get_device_id("src/main.rs").unwrap()
+        );
         if cfg!(unix) {
-            assert_ne!("", get_device_id(&"Cargo.toml".to_string()));
+            assert!(get_device_id("does/not/exist").is_err());
         }
     }
 
@@ -183,9 +207,16 @@ mod test {
     fn test_get_sector_size() {
         // this should be true for any platform where this test runs
         // but it doesn't exercise all platform variants
-        // let cwd = env::current_dir().unwrap();
-        // let test_string = cwd.into_os_string().into_string().unwrap();
-        // info!("{}", test_string);
-        // assert_ne!(0, get_sector_size(&test_string));
+        let sector_size = get_sector_size("Cargo.toml").unwrap();
+        assert!(sector_size >= 512);
+        assert_eq!(sector_size % 512, 0);
+    }
+
+    #[test]
+    fn test_is_rotational() {
+        assert!(is_rotational("Cargo.toml").is_ok());
+        if cfg!(target_os = "linux") {
+            assert!(is_rotational("does/not/exist").is_err());
+        }
     }
 }
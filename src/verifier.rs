@@ -0,0 +1,277 @@
+use crate::plot::{Plot, NONCE_SIZE, SCOOPS_IN_NONCE, SCOOP_SIZE};
+use crate::plotter::noncegen;
+use crate::utils::new_thread_pool;
+use rand::seq::index;
+use rand::thread_rng;
+use rayon::prelude::*;
+use std::fs::read_dir;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// nonces are regenerated in blocks so that all simd lanes are used
+const NONCES_PER_BLOCK: u64 = 16;
+
+/// Exit codes of the verify subcommand.
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_MISMATCH: i32 = 1;
+pub const EXIT_ERROR: i32 = 2;
+
+/// A sampled nonce whose scoops differ from the regenerated ones.
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    pub nonce: u64,
+    pub scoops: Vec<u32>,
+}
+
+pub struct Verifier {
+    pool: rayon::ThreadPool,
+    rate: f64,
+}
+
+impl Verifier {
+    pub fn new(rate: f64, cpu_threads: usize, thread_pinning: bool) -> Verifier {
+        Verifier {
+            pool: new_thread_pool(cpu_threads, thread_pinning),
+            rate,
+        }
+    }
+
+    /// Verifies a plot file or all plot files in a directory and returns the exit code.
+    pub fn run(&self, path: &Path) -> i32 {
+        let files: Vec<PathBuf> = if path.is_dir() {
+            match read_dir(path) {
+                Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+                Err(e) => {
+                    error!("verify: can't read {}: {}", path.to_str().unwrap(), e);
+                    return EXIT_ERROR;
+                }
+            }
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        let mut plots = 0;
+        let mut exit_code = EXIT_OK;
+        for file in files {
+            let mut plot = match Plot::new(&file, false, false) {
+                Ok(plot) => plot,
+                Err(e) => {
+                    // only complain about files that were asked for explicitly
+                    if !path.is_dir() {
+                        error!("verify: {}: {}", file.to_str().unwrap(), e);
+                        exit_code = EXIT_ERROR;
+                    }
+                    continue;
+                }
+            };
+            plots += 1;
+
+            match self.verify_plot(&mut plot) {
+                Ok((sampled, mismatches)) => {
+                    if mismatches.is_empty() {
+                        info!("verify: file={}, nonces={}, ok", plot.meta.name, sampled);
+                    } else {
+                        for m in &mismatches {
+                            error!(
+                                "verify: mismatch, file={}, nonce={}, scoops={}",
+                                plot.meta.name,
+                                plot.meta.start_nonce + m.nonce,
+                                format_scoops(&m.scoops)
+                            );
+                        }
+                        error!(
+                            "verify: file={}, nonces={}, corrupted nonces={}",
+                            plot.meta.name,
+                            sampled,
+                            mismatches.len()
+                        );
+                        if exit_code == EXIT_OK {
+                            exit_code = EXIT_MISMATCH;
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("verify: error reading {}: {}", plot.meta.name, e);
+                    exit_code = EXIT_ERROR;
+                }
+            }
+        }
+
+        if plots == 0 {
+            if path.is_dir() {
+                error!("verify: no plots in {}", path.to_str().unwrap());
+            }
+            return EXIT_ERROR;
+        }
+        exit_code
+    }
+
+    /// Regenerates randomly chosen nonces and compares them scoop by scoop with the plot.
+    /// Returns the number of sampled nonces and the nonces that differ.
+    pub fn verify_plot(&self, plot: &mut Plot) -> io::Result<(u64, Vec<Mismatch>)> {
+        let nonces = plot.meta.nonces;
+        let blocks = (nonces + NONCES_PER_BLOCK - 1) / NONCES_PER_BLOCK;
+        let sampled_blocks =
+            ((nonces as f64 * self.rate / NONCES_PER_BLOCK as f64).ceil() as u64).min(blocks);
+        let mut sampled_blocks: Vec<u64> = index::sample(
+            &mut thread_rng(),
+            blocks as usize,
+            sampled_blocks.max(1) as usize,
+        )
+        .into_iter()
+        .map(|b| b as u64)
+        .collect();
+        sampled_blocks.sort();
+
+        let account_id = plot.meta.account_id;
+        let start_nonce = plot.meta.start_nonce;
+        let plot = Mutex::new(plot);
+        let results: Vec<io::Result<(u64, Vec<Mismatch>)>> = self.pool.install(|| {
+            sampled_blocks
+                .par_iter()
+                .map(|block| {
+                    let offset = block * NONCES_PER_BLOCK;
+                    let block_nonces = NONCES_PER_BLOCK.min(nonces - offset);
+                    let mut expected = vec![0u8; (block_nonces * NONCE_SIZE) as usize];
+                    unsafe {
+                        noncegen(
+                            expected.as_mut_ptr(),
+                            block_nonces as usize,
+                            0,
+                            account_id,
+                            start_nonce + offset,
+                            block_nonces,
+                        );
+                    }
+
+                    let mut plot = plot.lock().unwrap();
+                    compare_block(&mut plot, &expected, offset, block_nonces)
+                        .map(|mismatches| (block_nonces, mismatches))
+                })
+                .collect()
+        });
+
+        let mut sampled = 0;
+        let mut mismatches = Vec::new();
+        for result in results {
+            let (block_nonces, block_mismatches) = result?;
+            sampled += block_nonces;
+            mismatches.extend(block_mismatches);
+        }
+        Ok((sampled, mismatches))
+    }
+}
+
+fn compare_block(
+    plot: &mut Plot,
+    expected: &[u8],
+    offset: u64,
+    block_nonces: u64,
+) -> io::Result<Vec<Mismatch>> {
+    let scoop_size = SCOOP_SIZE as usize;
+    let block_size = block_nonces as usize * scoop_size;
+    let mut bs = vec![0u8; block_size];
+    let mut mismatches: Vec<Mismatch> = (0..block_nonces)
+        .map(|n| Mismatch {
+            nonce: offset + n,
+            scoops: Vec::new(),
+        })
+        .collect();
+
+    for scoop in 0..SCOOPS_IN_NONCE as u32 {
+        plot.prepare_nonce(scoop, offset)?;
+        // reads of PoC1 plots stop at stagger group boundaries
+        let mut filled = 0;
+        while filled < block_size {
+            let mut chunk = vec![0u8; block_size - filled];
+            let (bytes_read, _, _) = plot.read(&mut chunk, scoop)?;
+            if bytes_read == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("short read in scoop {}", scoop),
+                ));
+            }
+            bs[filled..filled + bytes_read].copy_from_slice(&chunk[..bytes_read]);
+            filled += bytes_read;
+        }
+
+        let expected = &expected[scoop as usize * block_size..(scoop as usize + 1) * block_size];
+        for (n, mismatch) in mismatches.iter_mut().enumerate() {
+            let range = n * scoop_size..(n + 1) * scoop_size;
+            if bs[range.clone()] != expected[range] {
+                mismatch.scoops.push(scoop);
+            }
+        }
+    }
+
+    mismatches.retain(|m| !m.scoops.is_empty());
+    Ok(mismatches)
+}
+
+// collapses sorted scoop numbers into ranges, e.g. "0-3, 7"
+fn format_scoops(scoops: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for &scoop in scoops {
+        match ranges.last_mut() {
+            Some(last) if last.1 + 1 == scoop => last.1 = scoop,
+            _ => ranges.push((scoop, scoop)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{}-{}", first, last)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_format_scoops() {
+        assert_eq!(format_scoops(&[4095]), "4095");
+        assert_eq!(format_scoops(&[0, 1, 2, 3, 7, 9, 10]), "0-3, 7, 9-10");
+    }
+
+    #[test]
+    fn test_verify_plot() {
+        let dir = env::temp_dir().join("scavenger_test_verify");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        // cut the last two nonces out of the test plot, its last 8 bytes are zeroed out
+        let data = fs::read("test_data/10282355196851764065_0_8").unwrap();
+        let scoop_size = SCOOP_SIZE as usize;
+        let mut plot_data = Vec::new();
+        for scoop in 0..SCOOPS_IN_NONCE as usize {
+            plot_data.extend(&data[(scoop * 8 + 6) * scoop_size..(scoop + 1) * 8 * scoop_size]);
+        }
+        let path = dir.join("10282355196851764065_6_2");
+        fs::write(&path, &plot_data).unwrap();
+
+        let verifier = Verifier::new(1.0, 1, false);
+        let mut plot = Plot::new(&path, false, false).unwrap();
+        let (sampled, mismatches) = verifier.verify_plot(&mut plot).unwrap();
+        assert_eq!(sampled, 2);
+        assert_eq!(
+            mismatches,
+            vec![Mismatch {
+                nonce: 1,
+                scoops: vec![4095],
+            }]
+        );
+        assert_eq!(verifier.run(&dir), EXIT_MISMATCH);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}